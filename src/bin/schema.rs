@@ -1,11 +1,13 @@
 use cosmwasm_schema::write_api;
 
-use my_first_contract::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use my_first_contract::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, SudoMsg};
 
 fn main() {
     write_api! {
         instantiate: InstantiateMsg,
         execute: ExecuteMsg,
         query: QueryMsg,
+        migrate: MigrateMsg,
+        sudo: SudoMsg,
     }
 }
@@ -1,12 +1,13 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{to_json_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Addr, Uint128};
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use semver::Version;
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, GetDepositResponse, InstantiateMsg, QueryMsg, GetAllDepositResponse, GetTotalDepositResponse, GetStateResponse};
-use crate::state::{CONFIG, Config, BALANCES};
+use crate::msg::{AssetInfo, DenomParams, ExecuteMsg, SudoMsg, GetDepositResponse, GetDepositsResponse, InstantiateMsg, MigrateMsg, QueryMsg, GetAccountDepositsResponse, GetAllDepositResponse, GetTotalDepositResponse, AuditTotalDepositResponse, GetStateResponse, GetOwnershipResponse, DenomInfoResponse, ListDenomsResponse};
+use crate::state::{CONFIG, Config, BALANCES, TOTAL_DEPOSITS, PENDING_OWNER, DENOMS, DenomInfo, TOKEN};
 
-// version info for migration info hh
+// version info for migration info
 const CONTRACT_NAME: &str = "crates.io:my_first_contract";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -14,77 +15,241 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 pub fn instantiate(
     deps: DepsMut,
     _env: Env, 
-    _info: MessageInfo,
+    info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
+    let admin = match msg.admin {
+        Some(admin) => deps.api.addr_validate(&admin)?,
+        None => info.sender,
+    };
     let config = Config {
-        allowed_denom: msg.allowed_denom,
+        admin: Some(admin.clone()),
+        unexpected_funds: msg.unexpected_funds.unwrap_or_default(),
+        guardian: None,
+        shutdown: false,
+        membership: None,
     };
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     CONFIG.save(deps.storage, &config)?;
+    for denom in &msg.allowed_denoms {
+        DENOMS.save(deps.storage, denom, &DenomInfo::new(AssetInfo::Native(denom.clone()), DenomParams::default()))?;
+    }
 
     Ok(Response::new()
         .add_attribute("method", "instantiate")
-        .add_attribute("allowed denom", &config.allowed_denom))
+        .add_attribute("allowed denoms", msg.allowed_denoms.join(","))
+        .add_attribute("admin", admin))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Deposit {} => execute::deposit_fund(deps, info),
-        ExecuteMsg::Transfer {amount, receiver } => execute::transfer_fund(deps, info, amount, receiver),
-        ExecuteMsg::Withdraw {amount } => execute::withdraw_fund(deps, info, amount),
+        ExecuteMsg::Deposit { expected, min_credit } => execute::deposit_fund(deps, env, info, None, expected, min_credit),
+        ExecuteMsg::DepositFor { recipient } => execute::deposit_fund(deps, env, info, Some(recipient), None, None),
+        ExecuteMsg::DepositForMany { recipients } => execute::deposit_for_many(deps, env, info, recipients),
+        ExecuteMsg::Transfer {amount, denom, receiver, owner, memo, client_ref } => execute::transfer_fund(deps, env, info, amount, denom, receiver, owner, memo, client_ref),
+        ExecuteMsg::Withdraw {amount, denom, recipient, owner, memo, client_ref } => execute::withdraw_fund(deps, env, info, amount, denom, recipient, owner, memo, client_ref),
+        ExecuteMsg::WithdrawAll { recipient } => execute::withdraw_all(deps, env, info, recipient),
+        ExecuteMsg::EmergencyWithdraw {} => execute::emergency_withdraw(deps, env, info),
+        ExecuteMsg::ApproveOperator { operator, expires } => execute::approve_operator(deps, env, info, operator, expires),
+        ExecuteMsg::RevokeOperator { operator } => execute::revoke_operator(deps, info, operator),
+        ExecuteMsg::IncreaseAllowance { spender, denom, amount, expires } => execute::increase_allowance(deps, env, info, spender, denom, amount, expires),
+        ExecuteMsg::DecreaseAllowance { spender, denom, amount, expires } => execute::decrease_allowance(deps, env, info, spender, denom, amount, expires),
+        ExecuteMsg::TransferFrom { owner, receiver, amount, denom, client_ref } => execute::transfer_from(deps, env, info, owner, receiver, amount, denom, client_ref),
+        ExecuteMsg::WithdrawFrom { owner, amount, denom, recipient, client_ref } => execute::withdraw_from(deps, env, info, owner, amount, denom, recipient, client_ref),
+        ExecuteMsg::Receive(msg) => execute::receive_cw20(deps, env, info, msg),
+        ExecuteMsg::AddDenom { asset, params } => execute::add_denom(deps, info, asset, params),
+        ExecuteMsg::UpdateDenom { denom, params } => execute::update_denom(deps, info, denom, params),
+        ExecuteMsg::DisableDenom { denom } => execute::set_denom_enabled(deps, info, denom, false),
+        ExecuteMsg::EnableDenom { denom } => execute::set_denom_enabled(deps, info, denom, true),
+        ExecuteMsg::RemoveDenom { denom } => execute::remove_denom(deps, env, info, denom),
+        ExecuteMsg::UpdateConfig { unexpected_funds } => execute::update_config(deps, info, unexpected_funds),
+        ExecuteMsg::SetTokenInfo { denom, name, symbol, decimals } => execute::set_token_info(deps, info, denom, name, symbol, decimals),
+        ExecuteMsg::Pause { operations, until } => execute::pause(deps, env, info, operations, until),
+        ExecuteMsg::Unpause { operations } => execute::unpause(deps, info, operations),
+        ExecuteMsg::SetGuardian { guardian } => execute::set_guardian(deps, info, guardian),
+        ExecuteMsg::SetMembership { membership } => execute::set_membership(deps, info, membership),
+        ExecuteMsg::Freeze { address, reason } => execute::freeze(deps, env, info, address, reason),
+        ExecuteMsg::Unfreeze { address } => execute::unfreeze(deps, info, address),
+        ExecuteMsg::Shutdown {} => execute::shutdown(deps, info),
+        ExecuteMsg::SweepSurplus { recipient } => execute::sweep_surplus(deps, env, info, recipient),
+        ExecuteMsg::UpdateAdmin { admin } => execute::update_admin(deps, info, admin),
+        ExecuteMsg::RenounceAdmin {} => execute::renounce_admin(deps, info),
+        ExecuteMsg::ProposeNewOwner { owner, expiry } => execute::propose_new_owner(deps, env, info, owner, expiry),
+        ExecuteMsg::AcceptOwnership {} => execute::accept_ownership(deps, env, info),
+        ExecuteMsg::CancelOwnershipTransfer {} => execute::cancel_ownership_transfer(deps, info),
     }
 }
 
 pub mod execute {
-    use cosmwasm_std::{BankMsg, Coin};
+    use cosmwasm_std::{from_json, BankMsg, Coin, CosmosMsg, Empty, Event, Order, StdError, Storage, WasmMsg};
+    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+    use cw4::{Cw4QueryMsg, MemberResponse};
+    use cw_utils::Expiration;
 
-    use crate::state::BALANCES;
+    use crate::msg::{LedgerEntry, LedgerKind, Operation, ReceiveMsg, UnexpectedFundsPolicy};
+    use crate::state::{Allowance, FreezeInfo, PendingOwner, TokenConfig, ALLOWANCES, ALLOWANCES_SPENDER, CLIENT_REFS, FROZEN, HISTORY, LEDGER_SEQ, OPERATORS, PAUSED};
+
+    use crate::state::{BALANCES, HOLDERS};
 
     use super::*;
 
     pub fn deposit_fund(
         deps: DepsMut, 
+        env: Env,
         info: MessageInfo,
+        recipient: Option<String>,
+        expected: Option<Uint128>,
+        min_credit: Option<Uint128>,
     ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Deposit)?;
         let config = CONFIG.load(deps.storage)?;
-    
-        // Find the amount of allowed_denom sent with the transaction
-        let amount = info
-            .funds // funds sent
-            .iter() // iterate over coins sent
-            // TODO: Check if the && is correct
-            .find(|c| c.denom == config.allowed_denom) // search for the coin which is allowed by the contract
-            .map(|c| c.amount) // get the amount
-            .unwrap_or_else(Uint128::zero); // if not found return 0
-    
-        if amount.is_zero() {
+        let depositor = info.sender;
+        assert_not_frozen(deps.storage, &depositor)?;
+        let recipient = match recipient {
+            Some(recipient) => deps.api.addr_validate(&recipient)?,
+            None => depositor.clone(),
+        };
+        assert_not_frozen(deps.storage, &recipient)?;
+        assert_member(deps.as_ref(), &config, &recipient)?;
+
+        // Credit the coins sent with the transaction whose denom is registered in the contract
+        let (accepted, unexpected) = sort_funds(deps.as_ref(), info.funds)?;
+        assert_unexpected_funds_allowed(&config, &unexpected)?;
+        if accepted.is_empty() {
             return Err(ContractError::InvalidDepositAmount{});
         }
+        // An amount assertion names no denom, it only makes sense for a single one
+        if (expected.is_some() || min_credit.is_some()) && accepted.len() > 1 {
+            return Err(ContractError::SingleDenomRequired {});
+        }
+
+        let mut credited = vec![];
+        for (coin, denom_info) in accepted {
+            assert_deposit_amount(&coin, expected, min_credit)?;
+            credit_deposit(deps.storage, env.block.height, &recipient, &coin.denom, coin.amount, &denom_info)?;
+            let counterparty = (recipient != depositor).then(|| depositor.clone());
+            record_history(deps.storage, &env, &recipient, LedgerKind::Deposit, counterparty, coin.clone(), None)?;
+            credited.push(coin);
+        }
     
-        let depositor = info.sender;
-        BALANCES.update(deps.storage, depositor.clone(), |balance: Option<Uint128>| {
-            Ok::<Uint128,ContractError>(balance.unwrap_or_else(Uint128::zero) + amount)
-        })?;
-    
-        Ok(Response::new()
+        let mut response = Response::new()
             .add_attribute("action", "deposit_funds")
-            .add_attribute("depositor", depositor)
-            .add_attribute("amount", amount))
+            .add_attribute("depositor", &depositor)
+            .add_attribute("amount", coins_to_string(&credited));
+        if recipient != depositor {
+            response = response.add_event(Event::new("deposit_for")
+                .add_attribute("recipient", recipient)
+                .add_attribute("amount", coins_to_string(&credited)));
+        }
+        Ok(refund_unexpected_funds(response, &depositor, unexpected))
+    }
+
+    pub fn deposit_for_many(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        recipients: Vec<(String, Uint128)>,
+    ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Deposit)?;
+        let config = CONFIG.load(deps.storage)?;
+        let depositor = info.sender;
+        assert_not_frozen(deps.storage, &depositor)?;
+
+        let (mut accepted, unexpected) = sort_funds(deps.as_ref(), info.funds)?;
+        assert_unexpected_funds_allowed(&config, &unexpected)?;
+        if accepted.len() > 1 {
+            return Err(ContractError::SingleDenomRequired {});
+        }
+        let Some((coin, denom_info)) = accepted.pop() else {
+            return Err(ContractError::InvalidDepositAmount {});
+        };
+
+        let mut total = Uint128::zero();
+        let mut credits = vec![];
+        for (recipient, amount) in recipients {
+            if amount.is_zero() {
+                return Err(ContractError::InvalidDepositAmount {});
+            }
+            total = total.checked_add(amount).map_err(StdError::from)?;
+            let recipient = deps.api.addr_validate(&recipient)?;
+            assert_not_frozen(deps.storage, &recipient)?;
+            assert_member(deps.as_ref(), &config, &recipient)?;
+            credits.push((recipient, amount));
+        }
+        if total != coin.amount {
+            return Err(ContractError::DepositSplitMismatch {
+                denom: coin.denom,
+                total,
+                received: coin.amount,
+            });
+        }
+
+        let mut response = Response::new()
+            .add_attribute("action", "deposit_for_many")
+            .add_attribute("depositor", &depositor)
+            .add_attribute("amount", coin.to_string());
+        for (recipient, amount) in credits {
+            credit_deposit(deps.storage, env.block.height, &recipient, &coin.denom, amount, &denom_info)?;
+            let counterparty = (recipient != depositor).then(|| depositor.clone());
+            record_history(deps.storage, &env, &recipient, LedgerKind::Deposit, counterparty, Coin::new(amount, &coin.denom), None)?;
+            response = response.add_event(Event::new("deposit_for")
+                .add_attribute("recipient", recipient)
+                .add_attribute("amount", Coin::new(amount, &coin.denom).to_string()));
+        }
+        Ok(refund_unexpected_funds(response, &depositor, unexpected))
     }
 
+    pub fn receive_cw20(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        wrapper: Cw20ReceiveMsg,
+    ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Deposit)?;
+        // The sender of the hook is the token contract itself
+        let token = info.sender;
+        let denom_info = match DENOMS.may_load(deps.storage, token.as_str())? {
+            Some(denom_info) if denom_info.asset == AssetInfo::Cw20(token.clone()) => denom_info,
+            _ => return Err(ContractError::DenomNotFound { denom: token.to_string() }),
+        };
+        let depositor = deps.api.addr_validate(&wrapper.sender)?;
+        assert_not_frozen(deps.storage, &depositor)?;
+        assert_member(deps.as_ref(), &CONFIG.load(deps.storage)?, &depositor)?;
+
+        match from_json(&wrapper.msg)? {
+            ReceiveMsg::Deposit {} => {
+                if wrapper.amount.is_zero() {
+                    return Err(ContractError::InvalidDepositAmount {});
+                }
+                credit_deposit(deps.storage, env.block.height, &depositor, token.as_str(), wrapper.amount, &denom_info)?;
+                record_history(deps.storage, &env, &depositor, LedgerKind::Deposit, None, Coin::new(wrapper.amount, token.as_str()), None)?;
+
+                Ok(Response::new()
+                    .add_attribute("action", "deposit_funds")
+                    .add_attribute("depositor", depositor)
+                    .add_attribute("amount", format!("{}{}", wrapper.amount, token)))
+            }
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
     pub fn transfer_fund(
         deps: DepsMut,
+        env: Env,
         info: MessageInfo, 
         amount: Uint128,
+        denom: String,
         receiver: String,
+        owner: Option<String>,
+        memo: Option<String>,
+        client_ref: Option<String>,
     ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Transfer)?;
 
         // check no funds are sent
         if !info.funds.is_empty() {
@@ -92,449 +257,3562 @@ pub mod execute {
         }
 
         let receiver = deps.api.addr_validate(&receiver)?;
+        let account = account_for(deps.as_ref(), &env, &info.sender, owner)?;
+        assert_not_frozen(deps.storage, &info.sender)?;
+        assert_not_frozen(deps.storage, &account)?;
+        assert_not_frozen(deps.storage, &receiver)?;
+        assert_member(deps.as_ref(), &CONFIG.load(deps.storage)?, &receiver)?;
 
         if amount.is_zero() {
             return Err(ContractError::InvalidTransferAmount{});
         }
+        assert_new_reference(deps.as_ref(), &info.sender, client_ref.as_deref())?;
 
-        // check if deposits are sufficient
-        BALANCES.update(deps.storage, info.sender.clone(), |balance: Option<Uint128>| {
-            if let Some(balance_sender) = balance{
-                if balance_sender >= amount {
-                    Ok::<Uint128,ContractError>(balance_sender - amount)
-                } else {
-                    Err(ContractError::TransferFundsExceedsBalance {  })
-                }
-            } else {
-                Err(ContractError::AddressHasNotDeposit {  })
-            }
-        })?;
-        // TODO: Check if the error above block also this update
-        BALANCES.update(deps.storage, receiver.clone(), |balance: Option<Uint128>| {
-            Ok::<Uint128,ContractError>(balance.unwrap_or_else(Uint128::zero) + amount)
-        })?;
+        let id = transfer_balance(deps.storage, &env, &account, &receiver, amount, &denom, memo.clone())?;
+        save_reference(deps.storage, &info.sender, &account, client_ref.as_deref(), id)?;
 
-        Ok(Response::new()
+        let response = Response::new()
         .add_attribute("action", "trasfer_fund")
-        .add_attribute("sender", info.sender)
+        .add_attribute("sender", &account)
         .add_attribute("receiver", receiver)
-        .add_attribute("amount", amount.to_string()))
+        .add_attribute("amount", Coin::new(amount, denom).to_string());
+        Ok(add_reference_attributes(add_operator_attribute(response, &account, info.sender), memo, client_ref))
     }
 
-    pub fn withdraw_fund(
+    #[allow(clippy::too_many_arguments)]
+    pub fn transfer_from(
         deps: DepsMut,
-        info: MessageInfo, 
+        env: Env,
+        info: MessageInfo,
+        owner: String,
+        receiver: String,
         amount: Uint128,
+        denom: String,
+        client_ref: Option<String>,
     ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Transfer)?;
 
         // check no funds are sent
         if !info.funds.is_empty() {
             return Err(ContractError::NoEmptyFunds {});
         }
 
-        // Upload confuguration and balance
-        let config = CONFIG.load(deps.storage)?;
+        let owner = deps.api.addr_validate(&owner)?;
+        let receiver = deps.api.addr_validate(&receiver)?;
+        assert_not_frozen(deps.storage, &info.sender)?;
+        assert_not_frozen(deps.storage, &owner)?;
+        assert_not_frozen(deps.storage, &receiver)?;
+        assert_member(deps.as_ref(), &CONFIG.load(deps.storage)?, &receiver)?;
 
-        // Find the amount of allowed_denom sent with the transaction
         if amount.is_zero() {
-            return Err(ContractError::InvalidDepositAmount {});
+            return Err(ContractError::InvalidTransferAmount {});
         }
-
-        let receiver = info.sender.clone();
-        
-        // Update balance if sufficient amount was deposited.
-        BALANCES.update(deps.storage, info.sender.clone(), |balance: Option<Uint128>| {
-            if let Some(balance_sender) = balance{
-                if balance_sender >= amount {
-                    Ok::<Uint128,ContractError>(balance_sender - amount)
-                } else {
-                    Err(ContractError::WithdrawFundsExceedsBalance {  })
-                }
-            } else {
-                Err(ContractError::AddressHasNotDeposit {  })
-            }
-        })?;
-        Ok(Response::new().add_attribute("action", "withdraw").add_message(BankMsg::Send{ 
-            amount: vec![Coin::new(amount, config.allowed_denom.clone())], 
-            to_address: receiver.to_string(),
-        }).add_attribute("amount", amount)
-        .add_attribute("receiver", receiver))
+        assert_new_reference(deps.as_ref(), &info.sender, client_ref.as_deref())?;
+
+        deduct_allowance(deps.storage, &env, &owner, &info.sender, &denom, amount)?;
+        let id = transfer_balance(deps.storage, &env, &owner, &receiver, amount, &denom, None)?;
+        save_reference(deps.storage, &info.sender, &owner, client_ref.as_deref(), id)?;
+
+        let response = Response::new()
+            .add_attribute("action", "transfer_from")
+            .add_attribute("owner", owner)
+            .add_attribute("spender", info.sender)
+            .add_attribute("receiver", receiver)
+            .add_attribute("amount", Coin::new(amount, denom).to_string());
+        Ok(add_reference_attributes(response, None, client_ref))
     }
-}
 
-#[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(
-    deps: Deps,
-    _env: Env, 
-    msg: QueryMsg) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::GetState {} => to_json_binary(&query::state(deps)?),
-        QueryMsg::GetDeposit {owner} => to_json_binary(&query::deposit(deps, owner)?),
-        QueryMsg::GetAllDeposit {} => to_json_binary(&query::all_deposits(deps)?),
-        QueryMsg::GetTotalDeposit {} => to_json_binary(&query::totaldeposit(deps)?),
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_fund(
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo, 
+        amount: Uint128,
+        denom: String,
+        recipient: Option<String>,
+        owner: Option<String>,
+        memo: Option<String>,
+        client_ref: Option<String>,
+    ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Withdraw)?;
+
+        // check no funds are sent
+        if !info.funds.is_empty() {
+            return Err(ContractError::NoEmptyFunds {});
         }
+
+        if amount.is_zero() {
+            return Err(ContractError::InvalidWithdrawAmount {});
+        }
+        let account = account_for(deps.as_ref(), &env, &info.sender, owner)?;
+        assert_not_frozen(deps.storage, &info.sender)?;
+        assert_not_frozen(deps.storage, &account)?;
+        assert_new_reference(deps.as_ref(), &info.sender, client_ref.as_deref())?;
+
+        let receiver = match recipient {
+            Some(recipient) => deps.api.addr_validate(&recipient)?,
+            None => account.clone(),
+        };
+
+        let (id, payout) = withdraw_balance(deps.branch(), &env, &account, &receiver, amount, &denom, memo.clone())?;
+        save_reference(deps.storage, &info.sender, &account, client_ref.as_deref(), id)?;
+        let response = Response::new().add_attribute("action", "withdraw")
+        .add_message(payout)
+        .add_attribute("amount", Coin::new(amount, denom).to_string())
+        .add_attribute("receiver", receiver);
+        Ok(add_reference_attributes(add_operator_attribute(response, &account, info.sender), memo, client_ref))
     }
 
-pub mod query {
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_from(
+        mut deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        owner: String,
+        amount: Uint128,
+        denom: String,
+        recipient: Option<String>,
+        client_ref: Option<String>,
+    ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Withdraw)?;
 
-    use cosmwasm_std::Order;
+        // check no funds are sent
+        if !info.funds.is_empty() {
+            return Err(ContractError::NoEmptyFunds {});
+        }
 
-    use super::*;
+        if amount.is_zero() {
+            return Err(ContractError::InvalidWithdrawAmount {});
+        }
 
-    pub fn state(
-        deps: Deps) -> StdResult<GetStateResponse> {
-        let config = CONFIG.load(deps.storage)?;
-        Ok(GetStateResponse {allowed_denom: config.allowed_denom})
+        let owner = deps.api.addr_validate(&owner)?;
+        assert_not_frozen(deps.storage, &info.sender)?;
+        assert_not_frozen(deps.storage, &owner)?;
+        // Funds are sent to the spender unless another recipient is given
+        let receiver = match recipient {
+            Some(recipient) => deps.api.addr_validate(&recipient)?,
+            None => info.sender.clone(),
+        };
+
+        assert_new_reference(deps.as_ref(), &info.sender, client_ref.as_deref())?;
+
+        deduct_allowance(deps.storage, &env, &owner, &info.sender, &denom, amount)?;
+        let (id, payout) = withdraw_balance(deps.branch(), &env, &owner, &receiver, amount, &denom, None)?;
+        save_reference(deps.storage, &info.sender, &owner, client_ref.as_deref(), id)?;
+
+        let response = Response::new()
+            .add_message(payout)
+            .add_attribute("action", "withdraw_from")
+            .add_attribute("owner", owner)
+            .add_attribute("spender", info.sender)
+            .add_attribute("amount", Coin::new(amount, denom).to_string())
+            .add_attribute("receiver", receiver);
+        Ok(add_reference_attributes(response, None, client_ref))
     }
 
-    pub fn deposit(
-        deps: Deps, 
-        owner: Addr) -> StdResult<GetDepositResponse> {
-        let balance = BALANCES.load(deps.storage, owner.clone())?;
-        Ok(GetDepositResponse {address: owner, deposit: balance })
-    }
+    pub fn withdraw_all(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        recipient: Option<String>,
+    ) -> Result<Response, ContractError> {
+        assert_open(deps.as_ref(), &env, Operation::Withdraw)?;
 
-    pub fn all_deposits(deps: Deps) -> StdResult<Vec<GetAllDepositResponse>> {
-        // 
-        let balances = BALANCES.range(deps.storage, None, None, Order::Ascending); // LOAD MEGLIO
-    
-        let response = balances
-            .map(|item| {
-                let (address, balance) = item?;
-                Ok(GetAllDepositResponse {
-                    address: address,
-                    totaldeposit: balance,
-                })
-            })
+        // check no funds are sent
+        if !info.funds.is_empty() {
+            return Err(ContractError::NoEmptyFunds {});
+        }
+
+        assert_not_frozen(deps.storage, &info.sender)?;
+        let receiver = match recipient {
+            Some(recipient) => deps.api.addr_validate(&recipient)?,
+            None => info.sender.clone(),
+        };
+
+        let balances = BALANCES
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
             .collect::<StdResult<Vec<_>>>()?;
-    
-        Ok(response)
-    }
+        if balances.iter().all(|(_, amount)| amount.is_zero()) {
+            return Err(ContractError::AddressHasNotDeposit {});
+        }
 
-    pub fn totaldeposit(deps: Deps) -> StdResult<GetTotalDepositResponse> {
-        // Iterazione sui bilanci in ordine ascendente
-        let balances = BALANCES.range(deps.storage, None, None, Order::Ascending);
-    
-        // Calcolo del totale dei depositi con gestione degli errori
-        let total_deposit = balances
-            .fold(Uint128::zero(), |acc, item| {
-                let (_, balance) = item.expect("Item should be there");
-                acc + balance
+        // Native coins are sent together, CW20 tokens one transfer each
+        let mut paid = vec![];
+        let mut native = vec![];
+        let mut messages = vec![];
+        for (denom, amount) in balances {
+            BALANCES.remove(deps.storage, (&info.sender, &denom), env.block.height)?;
+            HOLDERS.remove(deps.storage, (&denom, &info.sender));
+            if amount.is_zero() {
+                continue;
+            }
+            decrease_total(deps.storage, env.block.height, &denom, amount)?;
+            let counterparty = (receiver != info.sender).then(|| receiver.clone());
+            record_history(deps.storage, &env, &info.sender, LedgerKind::Withdraw, counterparty, Coin::new(amount, &denom), None)?;
+            paid.push(Coin::new(amount, &denom));
+            match load_denom(deps.as_ref(), &denom)?.asset {
+                AssetInfo::Native(denom) => native.push(Coin::new(amount, denom)),
+                asset => messages.push(send_asset(&asset, &receiver, amount)?),
+            }
+        }
+
+        let mut response = Response::new()
+            .add_attribute("action", "withdraw_all")
+            .add_attribute("amount", coins_to_string(&paid))
+            .add_attribute("receiver", &receiver);
+        if !native.is_empty() {
+            response = response.add_message(BankMsg::Send {
+                to_address: receiver.to_string(),
+                amount: native,
             });
-    
-        Ok(GetTotalDepositResponse { totaldeposit: total_deposit })
+        }
+        Ok(response.add_messages(messages))
     }
-    
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{message_info, mock_dependencies, mock_env};
-    use cosmwasm_std::{coins, from_json};
+    pub fn emergency_withdraw(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        if !CONFIG.load(deps.storage)?.shutdown {
+            return Err(ContractError::NotShutDown {});
+        }
+        assert_not_paused(deps.as_ref(), &env, Operation::Withdraw)?;
+        assert_not_frozen(deps.storage, &info.sender)?;
 
+        // check no funds are sent
+        if !info.funds.is_empty() {
+            return Err(ContractError::NoEmptyFunds {});
+        }
 
-    // Istantiate
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
+        let balances = BALANCES
+            .prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        if balances.iter().all(|(_, amount)| amount.is_zero()) {
+            return Err(ContractError::AddressHasNotDeposit {});
+        }
 
-        // we can just call .unwrap() to assert this was a success
-        let res = instantiate(deps.as_mut(), mock_env(), info, msg);
-        assert!(res.is_ok());
-        assert_eq!(0, res.unwrap().messages.len());
+        // On a shortfall every depositor gets the same share of their balance, whatever the order they exit in
+        let mut paid = vec![];
+        let mut native = vec![];
+        let mut messages = vec![];
+        for (denom, amount) in balances {
+            BALANCES.remove(deps.storage, (&info.sender, &denom), env.block.height)?;
+            HOLDERS.remove(deps.storage, (&denom, &info.sender));
+            if amount.is_zero() {
+                continue;
+            }
+            let denom_info = load_denom(deps.as_ref(), &denom)?;
+            let held = query::held_balance(deps.as_ref(), &env, &denom_info.asset)?;
+            let total = TOTAL_DEPOSITS.load(deps.storage, &denom)?;
+            let payout = if held < total { amount.multiply_ratio(held, total) } else { amount };
+
+            decrease_total(deps.storage, env.block.height, &denom, amount)?;
+            record_history(deps.storage, &env, &info.sender, LedgerKind::Withdraw, None, Coin::new(amount, &denom), None)?;
+            if payout.is_zero() {
+                continue;
+            }
+            paid.push(Coin::new(payout, &denom));
+            match denom_info.asset {
+                AssetInfo::Native(denom) => native.push(Coin::new(payout, denom)),
+                asset => messages.push(send_asset(&asset, &info.sender, payout)?),
+            }
+        }
 
-        // query the state
-        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
-        let value: GetStateResponse = from_json(&res_q).unwrap();
-        assert_eq!("tsy", value.allowed_denom);
+        let mut response = Response::new()
+            .add_attribute("action", "emergency_withdraw")
+            .add_attribute("amount", coins_to_string(&paid))
+            .add_attribute("receiver", &info.sender);
+        if !native.is_empty() {
+            response = response.add_message(BankMsg::Send {
+                to_address: info.sender.to_string(),
+                amount: native,
+            });
+        }
+        Ok(response.add_messages(messages))
     }
 
-    // Test deposit successful
-    #[test]
-    fn test_deposit_successfully() {
-        let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    pub fn shutdown(
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let mut config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+        if config.shutdown {
+            return Err(ContractError::ShutDown {});
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(res.messages.len(), 0);
+        config.shutdown = true;
+        CONFIG.save(deps.storage, &config)?;
 
-        // Check the balance in the storage
-        let balance = BALANCES.load(&deps.storage, Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0")).unwrap();
-        assert_eq!(balance, Uint128::new(1000));
-    
-        // Check if the correct event is emitted
-        // let events = res.events;
-        // assert_eq!(events.len(), 1);
-        // assert_eq!(events[0].ty, "deposit");
-        // assert_eq!(events[0].attributes.len(), 2);
-        // assert_eq!(events[0].attributes[0].key, "sender");
-        // assert_eq!(events[0].attributes[0].value, "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0");
-        // assert_eq!(events[0].attributes[1].key, "amount");
-        // assert_eq!(events[0].attributes[1].value, "2tsy");
+        Ok(Response::new().add_attribute("action", "shutdown"))
     }
 
-    // Test deposit error 0
-    #[test]
-    fn test_deposit_error_0_amount() {
-        let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
-
-        let msg = ExecuteMsg::Deposit{};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(0, "tsy".to_string()));
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
-        // assert!(res.is_err(),"Must return Invalid Deposit Amount error");
-        // let err = res.unwrap_err();
-        // assert_eq!(err, ContractError::InvalidDepositAmount{}, "Must return Invalid Deposit Amount error");
-        match res { 
-            Err(ContractError::InvalidDepositAmount {}) => {}
-            _ => panic!("Must return Invalid Deposit Amount error"),
+    pub fn approve_operator(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        operator: String,
+        expires: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        let operator = deps.api.addr_validate(&operator)?;
+        if operator == info.sender {
+            return Err(ContractError::CannotApproveSelf {});
+        }
+        let expires = expires.unwrap_or_default();
+        if expires.is_expired(&env.block) {
+            return Err(ContractError::InvalidOperatorExpiry {});
         }
+
+        // Approving again replaces the expiry of the grant
+        OPERATORS.save(deps.storage, (&info.sender, &operator), &expires)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "approve_operator")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator)
+            .add_attribute("expires", expires.to_string()))
     }
-    // Test deposit error 0 parte 2
-    #[test]
-    fn test_deposit_error_0_amount_not_matching_allowed_denom() {
-        let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "thi".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
 
-        let msg = ExecuteMsg::Deposit{};
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
-        match res {
-            Err(ContractError::InvalidDepositAmount {}) => {}
-            _ => panic!("Must return Invalid Deposit Amount error"),
+    pub fn revoke_operator(
+        deps: DepsMut,
+        info: MessageInfo,
+        operator: String,
+    ) -> Result<Response, ContractError> {
+        let operator = deps.api.addr_validate(&operator)?;
+        if !OPERATORS.has(deps.storage, (&info.sender, &operator)) {
+            return Err(ContractError::OperatorNotFound { operator: operator.to_string() });
         }
+        OPERATORS.remove(deps.storage, (&info.sender, &operator));
+
+        Ok(Response::new()
+            .add_attribute("action", "revoke_operator")
+            .add_attribute("owner", info.sender)
+            .add_attribute("operator", operator))
     }
 
-    // Test transfer successfully
-    #[test]
-    fn test_transfer_successfully() {
-        let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("sender1"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    pub fn increase_allowance(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        spender: String,
+        denom: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        let spender = deps.api.addr_validate(&spender)?;
+        if spender == info.sender {
+            return Err(ContractError::CannotApproveSelf {});
+        }
+        if let Some(expires) = expires {
+            if expires.is_expired(&env.block) {
+                return Err(ContractError::InvalidAllowanceExpiry {});
+            }
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // An expired allowance starts again from zero
+        let allowance = ALLOWANCES
+            .may_load(deps.storage, (&info.sender, &spender, &denom))?
+            .filter(|allowance| !allowance.expires.is_expired(&env.block))
+            .unwrap_or_default();
+        let allowance = Allowance {
+            amount: allowance.amount.checked_add(amount).map_err(StdError::from)?,
+            expires: expires.unwrap_or(allowance.expires),
+        };
+        save_allowance(deps.storage, &info.sender, &spender, &denom, &allowance)?;
 
-        let msg = ExecuteMsg::Transfer {receiver: "sender1".to_string(), amount: Uint128::new(2),};
-        let info = message_info(&Addr::unchecked("sender2"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
-        let res_unwrapped = res.unwrap();
-        assert_eq!(res_unwrapped.messages.len(), 0);
+        Ok(Response::new()
+            .add_attribute("action", "increase_allowance")
+            .add_attribute("owner", info.sender)
+            .add_attribute("spender", spender)
+            .add_attribute("amount", Coin::new(amount, denom).to_string()))
+    }
 
-        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: Addr::unchecked("sender2") }).unwrap();
-        let value: GetDepositResponse = from_json(&res_q).unwrap();
-        assert_eq!(value.deposit, Uint128::new(2));
+    pub fn decrease_allowance(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        spender: String,
+        denom: String,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        let spender = deps.api.addr_validate(&spender)?;
+        if spender == info.sender {
+            return Err(ContractError::CannotApproveSelf {});
+        }
+        if let Some(expires) = expires {
+            if expires.is_expired(&env.block) {
+                return Err(ContractError::InvalidAllowanceExpiry {});
+            }
+        }
 
-        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: Addr::unchecked("sender1") }).unwrap();
-        let value: GetDepositResponse = from_json(&res_q).unwrap();
-        assert_eq!(value.deposit, Uint128::new(998));
+        // Decreasing below zero removes the allowance
+        let allowance = ALLOWANCES
+            .may_load(deps.storage, (&info.sender, &spender, &denom))?
+            .unwrap_or_default();
+        let allowance = Allowance {
+            amount: allowance.amount.saturating_sub(amount),
+            expires: expires.unwrap_or(allowance.expires),
+        };
+        save_allowance(deps.storage, &info.sender, &spender, &denom, &allowance)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "decrease_allowance")
+            .add_attribute("owner", info.sender)
+            .add_attribute("spender", spender)
+            .add_attribute("amount", Coin::new(amount, denom).to_string()))
+    }
+
+    pub fn add_denom(
+        deps: DepsMut,
+        info: MessageInfo,
+        asset: AssetInfo,
+        params: DenomParams,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        if let AssetInfo::Cw20(contract) = &asset {
+            deps.api.addr_validate(contract.as_str())?;
+        }
+        let denom = asset.key().to_string();
+        if DENOMS.has(deps.storage, &denom) {
+            return Err(ContractError::DenomAlreadyRegistered { denom });
+        }
+        DENOMS.save(deps.storage, &denom, &DenomInfo::new(asset, params))?;
+
+        Ok(Response::new()
+            .add_attribute("action", "add_denom")
+            .add_attribute("denom", denom))
+    }
+
+    pub fn update_denom(
+        deps: DepsMut,
+        info: MessageInfo,
+        denom: String,
+        params: DenomParams,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        let denom_info = load_denom(deps.as_ref(), &denom)?;
+        DENOMS.save(deps.storage, &denom, &DenomInfo {
+            enabled: denom_info.enabled,
+            ..DenomInfo::new(denom_info.asset, params)
+        })?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_denom")
+            .add_attribute("denom", denom))
+    }
+
+    pub fn set_denom_enabled(
+        deps: DepsMut,
+        info: MessageInfo,
+        denom: String,
+        enabled: bool,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        let mut denom_info = load_denom(deps.as_ref(), &denom)?;
+        denom_info.enabled = enabled;
+        DENOMS.save(deps.storage, &denom, &denom_info)?;
+
+        let action = if enabled { "enable_denom" } else { "disable_denom" };
+        Ok(Response::new()
+            .add_attribute("action", action)
+            .add_attribute("denom", denom))
+    }
+
+    pub fn remove_denom(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        denom: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        load_denom(deps.as_ref(), &denom)?;
+        // Deposits of a removed denom could no longer be accounted for
+        let outstanding = TOTAL_DEPOSITS.may_load(deps.storage, &denom)?.unwrap_or_default();
+        if !outstanding.is_zero() {
+            return Err(ContractError::DepositsOutstanding {});
+        }
+        DENOMS.remove(deps.storage, &denom);
+        TOTAL_DEPOSITS.remove(deps.storage, &denom, env.block.height)?;
+        if matches!(TOKEN.may_load(deps.storage)?, Some(token) if token.denom == denom) {
+            TOKEN.remove(deps.storage);
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "remove_denom")
+            .add_attribute("denom", denom))
+    }
+
+    pub fn update_config(
+        deps: DepsMut,
+        info: MessageInfo,
+        unexpected_funds: Option<UnexpectedFundsPolicy>,
+    ) -> Result<Response, ContractError> {
+        let mut config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        if let Some(unexpected_funds) = unexpected_funds {
+            config.unexpected_funds = unexpected_funds;
+        }
+        CONFIG.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("action", "update_config")
+            .add_attribute("unexpected_funds", format!("{:?}", config.unexpected_funds)))
+    }
+
+    pub fn set_token_info(
+        deps: DepsMut,
+        info: MessageInfo,
+        denom: String,
+        name: Option<String>,
+        symbol: Option<String>,
+        decimals: Option<u8>,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        load_denom(deps.as_ref(), &denom)?;
+        TOKEN.save(deps.storage, &TokenConfig { denom: denom.clone(), name, symbol, decimals })?;
+
+        Ok(Response::new()
+            .add_attribute("action", "set_token_info")
+            .add_attribute("denom", denom))
+    }
+
+    pub fn pause(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        operations: Option<Vec<Operation>>,
+        until: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        // The guardian can pause, but only the admin can lift a pause
+        let config = CONFIG.load(deps.storage)?;
+        if config.guardian.as_ref() != Some(&info.sender) {
+            assert_admin(&config, &info.sender)?;
+        }
+        let extend_only = config.admin.as_ref() != Some(&info.sender);
+
+        pause_operations(deps.storage, &env, operations, until, extend_only)
+    }
+
+    // With extend_only a running pause is never shortened, so that only the admin can lift it
+    pub(crate) fn pause_operations(
+        storage: &mut dyn Storage,
+        env: &Env,
+        operations: Option<Vec<Operation>>,
+        until: Option<Expiration>,
+        extend_only: bool,
+    ) -> Result<Response, ContractError> {
+        let until = until.unwrap_or_default();
+        if until.is_expired(&env.block) {
+            return Err(ContractError::InvalidPauseExpiry {});
+        }
+        let operations = operations.unwrap_or_else(|| Operation::ALL.to_vec());
+        // The expiry stored for each operation, in the order of the operations attribute
+        let mut stored = vec![];
+        for operation in &operations {
+            let until = match PAUSED.may_load(storage, operation.key())? {
+                Some(current) if extend_only && !current.is_expired(&env.block) => later_expiry(current, until),
+                _ => until,
+            };
+            PAUSED.save(storage, operation.key(), &until)?;
+            stored.push(until.to_string());
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "pause")
+            .add_attribute("operations", operations_to_string(&operations))
+            .add_attribute("until", stored.join(",")))
+    }
+
+    pub fn unpause(
+        deps: DepsMut,
+        info: MessageInfo,
+        operations: Option<Vec<Operation>>,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        unpause_operations(deps.storage, operations)
+    }
+
+    pub(crate) fn unpause_operations(
+        storage: &mut dyn Storage,
+        operations: Option<Vec<Operation>>,
+    ) -> Result<Response, ContractError> {
+        let operations = operations.unwrap_or_else(|| Operation::ALL.to_vec());
+        for operation in &operations {
+            PAUSED.remove(storage, operation.key());
+        }
+
+        Ok(Response::new()
+            .add_attribute("action", "unpause")
+            .add_attribute("operations", operations_to_string(&operations)))
+    }
+
+    pub fn set_guardian(
+        deps: DepsMut,
+        info: MessageInfo,
+        guardian: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let mut config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        config.guardian = guardian.map(|guardian| deps.api.addr_validate(&guardian)).transpose()?;
+        CONFIG.save(deps.storage, &config)?;
+
+        let guardian = config.guardian.map(String::from).unwrap_or_default();
+        Ok(Response::new()
+            .add_attribute("action", "set_guardian")
+            .add_attribute("guardian", guardian))
+    }
+
+    pub fn set_membership(
+        deps: DepsMut,
+        info: MessageInfo,
+        membership: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let mut config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        config.membership = membership.map(|membership| deps.api.addr_validate(&membership)).transpose()?;
+        CONFIG.save(deps.storage, &config)?;
+
+        let membership = config.membership.map(String::from).unwrap_or_default();
+        Ok(Response::new()
+            .add_attribute("action", "set_membership")
+            .add_attribute("membership", membership))
+    }
+
+    pub fn freeze(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        address: String,
+        reason: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        freeze_account(deps, &env, address, reason)
+    }
+
+    pub fn unfreeze(
+        deps: DepsMut,
+        info: MessageInfo,
+        address: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        unfreeze_account(deps, address)
+    }
+
+    // Freezing again replaces the reason and the time of the freeze
+    pub(crate) fn freeze_account(
+        deps: DepsMut,
+        env: &Env,
+        address: String,
+        reason: String,
+    ) -> Result<Response, ContractError> {
+        let address = deps.api.addr_validate(&address)?;
+        FROZEN.save(deps.storage, &address, &FreezeInfo { reason: reason.clone(), since: env.block.time })?;
+
+        Ok(Response::new()
+            .add_attribute("action", "freeze")
+            .add_attribute("address", address)
+            .add_attribute("reason", reason))
+    }
+
+    pub(crate) fn unfreeze_account(
+        deps: DepsMut,
+        address: String,
+    ) -> Result<Response, ContractError> {
+        let address = deps.api.addr_validate(&address)?;
+        if !FROZEN.has(deps.storage, &address) {
+            return Err(ContractError::AccountNotFrozen { address: address.to_string() });
+        }
+        FROZEN.remove(deps.storage, &address);
+
+        Ok(Response::new()
+            .add_attribute("action", "unfreeze")
+            .add_attribute("address", address))
+    }
+
+    // Heights and times cannot be compared, the current expiry is then kept
+    fn later_expiry(current: Expiration, new: Expiration) -> Expiration {
+        match (current, new) {
+            (Expiration::AtHeight(current), Expiration::AtHeight(new)) => Expiration::AtHeight(current.max(new)),
+            (Expiration::AtTime(current), Expiration::AtTime(new)) => Expiration::AtTime(current.max(new)),
+            (_, Expiration::Never {}) => Expiration::Never {},
+            (current, _) => current,
+        }
+    }
+
+    fn operations_to_string(operations: &[Operation]) -> String {
+        operations.iter().map(|operation| operation.key()).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn sweep_surplus(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        recipient: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        // check no funds are sent
+        if !info.funds.is_empty() {
+            return Err(ContractError::NoEmptyFunds {});
+        }
+        let recipient = deps.api.addr_validate(&recipient)?;
+
+        // Only what exceeds the deposits is sent, deposits stay covered
+        let mut native = vec![];
+        let mut messages = vec![];
+        for solvency in query::solvency(deps.as_ref(), &env)?.denoms {
+            if solvency.surplus.is_zero() {
+                continue;
+            }
+            match load_denom(deps.as_ref(), &solvency.denom)?.asset {
+                AssetInfo::Native(denom) => native.push(Coin::new(solvency.surplus, denom)),
+                asset => messages.push(send_asset(&asset, &recipient, solvency.surplus)?),
+            }
+        }
+        if native.is_empty() && messages.is_empty() {
+            return Err(ContractError::NoSurplus {});
+        }
+
+        let mut response = Response::new()
+            .add_attribute("action", "sweep_surplus")
+            .add_attribute("amount", coins_to_string(&native))
+            .add_attribute("recipient", &recipient);
+        if !native.is_empty() {
+            response = response.add_message(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: native,
+            });
+        }
+        Ok(response.add_messages(messages))
+    }
+
+    pub fn update_admin(
+        deps: DepsMut,
+        info: MessageInfo,
+        admin: String,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        replace_admin(deps, config, admin)
+    }
+
+    // Also drops any pending ownership offer of the previous admin
+    pub(crate) fn replace_admin(
+        deps: DepsMut,
+        mut config: Config,
+        admin: String,
+    ) -> Result<Response, ContractError> {
+        let admin = deps.api.addr_validate(&admin)?;
+        config.admin = Some(admin.clone());
+        CONFIG.save(deps.storage, &config)?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("action", "update_admin")
+            .add_attribute("admin", admin))
+    }
+
+    pub fn renounce_admin(
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let mut config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        config.admin = None;
+        CONFIG.save(deps.storage, &config)?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new().add_attribute("action", "renounce_admin"))
+    }
+
+    pub fn propose_new_owner(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        owner: String,
+        expiry: Option<Expiration>,
+    ) -> Result<Response, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        assert_admin(&config, &info.sender)?;
+
+        let owner = deps.api.addr_validate(&owner)?;
+        let expiry = expiry.unwrap_or_default();
+        if expiry.is_expired(&env.block) {
+            return Err(ContractError::InvalidExpiry {});
+        }
+
+        // A new proposal replaces any previous one
+        PENDING_OWNER.save(deps.storage, &PendingOwner { owner: owner.clone(), expiry })?;
+
+        Ok(Response::new()
+            .add_attribute("action", "propose_new_owner")
+            .add_attribute("pending_owner", owner)
+            .add_attribute("expiry", expiry.to_string()))
+    }
+
+    pub fn accept_ownership(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pending = PENDING_OWNER
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoPendingOwner {})?;
+        if pending.owner != info.sender {
+            return Err(ContractError::Unauthorized {});
+        }
+        if pending.expiry.is_expired(&env.block) {
+            return Err(ContractError::OwnershipProposalExpired {});
+        }
+
+        let mut config = CONFIG.load(deps.storage)?;
+        config.admin = Some(pending.owner.clone());
+        CONFIG.save(deps.storage, &config)?;
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("action", "accept_ownership")
+            .add_attribute("admin", pending.owner))
+    }
+
+    pub fn cancel_ownership_transfer(
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pending = PENDING_OWNER
+            .may_load(deps.storage)?
+            .ok_or(ContractError::NoPendingOwner {})?;
+
+        // Both the admin and the proposed owner may withdraw the offer
+        if pending.owner != info.sender {
+            let config = CONFIG.load(deps.storage)?;
+            assert_admin(&config, &info.sender)?;
+        }
+        PENDING_OWNER.remove(deps.storage);
+
+        Ok(Response::new()
+            .add_attribute("action", "cancel_ownership_transfer")
+            .add_attribute("pending_owner", pending.owner))
+    }
+
+    fn load_denom(deps: Deps, denom: &str) -> Result<DenomInfo, ContractError> {
+        DENOMS
+            .may_load(deps.storage, denom)?
+            .ok_or_else(|| ContractError::DenomNotFound { denom: denom.to_string() })
+    }
+
+    // Applies the registry parameters of the denom to a deposit and credits it
+    fn credit_deposit(
+        storage: &mut dyn Storage,
+        height: u64,
+        depositor: &Addr,
+        denom: &str,
+        amount: Uint128,
+        denom_info: &DenomInfo,
+    ) -> Result<(), ContractError> {
+        if !denom_info.enabled {
+            return Err(ContractError::DenomDisabled { denom: denom.to_string() });
+        }
+        if let Some(min) = denom_info.min_deposit {
+            if amount < min {
+                return Err(ContractError::DepositBelowMinimum { denom: denom.to_string(), min });
+            }
+        }
+
+        let balance = BALANCES.update(storage, (depositor, denom), height, |balance: Option<Uint128>| {
+            Ok::<Uint128,ContractError>(balance.unwrap_or_else(Uint128::zero) + amount)
+        })?;
+        index_holder(storage, depositor, denom, balance)?;
+        assert_within_cap(denom_info, denom, balance)?;
+        increase_total(storage, height, denom, amount)?;
+        Ok(())
+    }
+
+    // Deposits, transfers and withdraws are closed for good once the contract is shut down
+    fn assert_open(deps: Deps, env: &Env, operation: Operation) -> Result<(), ContractError> {
+        if CONFIG.load(deps.storage)?.shutdown {
+            return Err(ContractError::ShutDown {});
+        }
+        assert_not_paused(deps, env, operation)
+    }
+
+    fn assert_not_paused(deps: Deps, env: &Env, operation: Operation) -> Result<(), ContractError> {
+        match PAUSED.may_load(deps.storage, operation.key())? {
+            Some(until) if !until.is_expired(&env.block) => Err(ContractError::Paused { operation: operation.key().to_string() }),
+            _ => Ok(()),
+        }
+    }
+
+    fn assert_not_frozen(storage: &dyn Storage, address: &Addr) -> Result<(), ContractError> {
+        if FROZEN.has(storage, address) {
+            return Err(ContractError::AccountFrozen { address: address.to_string() });
+        }
+        Ok(())
+    }
+
+    // Any weight, zero included, makes a member of the group
+    fn assert_member(deps: Deps, config: &Config, address: &Addr) -> Result<(), ContractError> {
+        let Some(membership) = &config.membership else {
+            return Ok(());
+        };
+        let member: MemberResponse = deps.querier.query_wasm_smart(membership, &Cw4QueryMsg::Member {
+            addr: address.to_string(),
+            at_height: None,
+        })?;
+        if member.weight.is_none() {
+            return Err(ContractError::NotAMember { address: address.to_string() });
+        }
+        Ok(())
+    }
+
+    // Account acted on: the owner when the sender is one of its operators, the sender's own otherwise
+    fn account_for(deps: Deps, env: &Env, sender: &Addr, owner: Option<String>) -> Result<Addr, ContractError> {
+        let owner = match owner {
+            Some(owner) => deps.api.addr_validate(&owner)?,
+            None => return Ok(sender.clone()),
+        };
+        if owner != *sender {
+            match OPERATORS.may_load(deps.storage, (&owner, sender))? {
+                Some(expires) if !expires.is_expired(&env.block) => {}
+                _ => return Err(ContractError::Unauthorized {}),
+            }
+        }
+        Ok(owner)
+    }
+
+    fn add_operator_attribute(response: Response, account: &Addr, sender: Addr) -> Response {
+        if *account == sender {
+            return response;
+        }
+        response.add_attribute("operator", sender)
+    }
+
+    // Moves a deposit between two accounts and returns the ledger entry id of the sender
+    fn transfer_balance(
+        storage: &mut dyn Storage,
+        env: &Env,
+        sender: &Addr,
+        receiver: &Addr,
+        amount: Uint128,
+        denom: &str,
+        memo: Option<String>,
+    ) -> Result<u64, ContractError> {
+        // check if deposits are sufficient
+        let balance = BALANCES.update(storage, (sender, denom), env.block.height, |balance: Option<Uint128>| {
+            if let Some(balance_sender) = balance{
+                if balance_sender >= amount {
+                    Ok::<Uint128,ContractError>(balance_sender - amount)
+                } else {
+                    Err(ContractError::TransferFundsExceedsBalance {  })
+                }
+            } else {
+                Err(ContractError::AddressHasNotDeposit {  })
+            }
+        })?;
+        index_holder(storage, sender, denom, balance)?;
+        // Only reached once the debit succeeded, and a failed execution reverts both writes
+        let balance = BALANCES.update(storage, (receiver, denom), env.block.height, |balance: Option<Uint128>| {
+            Ok::<Uint128,ContractError>(balance.unwrap_or_else(Uint128::zero) + amount)
+        })?;
+        index_holder(storage, receiver, denom, balance)?;
+        if let Some(denom_info) = DENOMS.may_load(storage, denom)? {
+            assert_within_cap(&denom_info, denom, balance)?;
+        }
+        let coin = Coin::new(amount, denom);
+        let id = record_history(storage, env, sender, LedgerKind::TransferOut, Some(receiver.clone()), coin.clone(), memo.clone())?;
+        record_history(storage, env, receiver, LedgerKind::TransferIn, Some(sender.clone()), coin, memo)?;
+        Ok(id)
+    }
+
+    // Debits a deposit and returns the ledger entry id of the owner with the message paying it out
+    fn withdraw_balance(
+        deps: DepsMut,
+        env: &Env,
+        owner: &Addr,
+        receiver: &Addr,
+        amount: Uint128,
+        denom: &str,
+        memo: Option<String>,
+    ) -> Result<(u64, CosmosMsg), ContractError> {
+        // Update balance if sufficient amount was deposited.
+        let balance = BALANCES.update(deps.storage, (owner, denom), env.block.height, |balance: Option<Uint128>| {
+            if let Some(balance_sender) = balance{
+                if balance_sender >= amount {
+                    Ok::<Uint128,ContractError>(balance_sender - amount)
+                } else {
+                    Err(ContractError::WithdrawFundsExceedsBalance {  })
+                }
+            } else {
+                Err(ContractError::AddressHasNotDeposit {  })
+            }
+        })?;
+        index_holder(deps.storage, owner, denom, balance)?;
+        decrease_total(deps.storage, env.block.height, denom, amount)?;
+        let counterparty = (receiver != owner).then(|| receiver.clone());
+        let id = record_history(deps.storage, env, owner, LedgerKind::Withdraw, counterparty, Coin::new(amount, denom), memo)?;
+        let denom_info = load_denom(deps.as_ref(), denom)?;
+        Ok((id, send_asset(&denom_info.asset, receiver, amount)?))
+    }
+
+    // Spends part of the allowance given by the owner to the spender
+    fn deduct_allowance(
+        storage: &mut dyn Storage,
+        env: &Env,
+        owner: &Addr,
+        spender: &Addr,
+        denom: &str,
+        amount: Uint128,
+    ) -> Result<(), ContractError> {
+        let mut allowance = ALLOWANCES
+            .may_load(storage, (owner, spender, denom))?
+            .ok_or_else(|| ContractError::InsufficientAllowance { denom: denom.to_string(), allowance: Uint128::zero() })?;
+        if allowance.expires.is_expired(&env.block) {
+            return Err(ContractError::AllowanceExpired {});
+        }
+        allowance.amount = allowance.amount.checked_sub(amount).map_err(|_| ContractError::InsufficientAllowance {
+            denom: denom.to_string(),
+            allowance: allowance.amount,
+        })?;
+        save_allowance(storage, owner, spender, denom, &allowance)?;
+        Ok(())
+    }
+
+    // Allowances are indexed both by owner and by spender, empty ones are removed
+    fn save_allowance(
+        storage: &mut dyn Storage,
+        owner: &Addr,
+        spender: &Addr,
+        denom: &str,
+        allowance: &Allowance,
+    ) -> StdResult<()> {
+        if allowance.amount.is_zero() {
+            ALLOWANCES.remove(storage, (owner, spender, denom));
+            ALLOWANCES_SPENDER.remove(storage, (spender, owner, denom));
+            return Ok(());
+        }
+        ALLOWANCES.save(storage, (owner, spender, denom), allowance)?;
+        ALLOWANCES_SPENDER.save(storage, (spender, owner, denom), allowance)
+    }
+
+    // Every deposit and withdraw goes through these, transfers leave the total unchanged
+    fn increase_total(storage: &mut dyn Storage, height: u64, denom: &str, amount: Uint128) -> StdResult<Uint128> {
+        TOTAL_DEPOSITS.update(storage, denom, height, |total| {
+            Ok(total.unwrap_or_default().checked_add(amount)?)
+        })
+    }
+
+    // Keeps HOLDERS in step with a deposit just written
+    fn index_holder(storage: &mut dyn Storage, owner: &Addr, denom: &str, balance: Uint128) -> StdResult<()> {
+        if balance.is_zero() {
+            HOLDERS.remove(storage, (denom, owner));
+            Ok(())
+        } else {
+            HOLDERS.save(storage, (denom, owner), &Empty {})
+        }
+    }
+
+    fn decrease_total(storage: &mut dyn Storage, height: u64, denom: &str, amount: Uint128) -> StdResult<Uint128> {
+        TOTAL_DEPOSITS.update(storage, denom, height, |total| {
+            Ok(total.unwrap_or_default().checked_sub(amount)?)
+        })
+    }
+
+    // Appends an entry to the ledger of the account and returns its id
+    fn record_history(
+        storage: &mut dyn Storage,
+        env: &Env,
+        account: &Addr,
+        kind: LedgerKind,
+        counterparty: Option<Addr>,
+        amount: Coin,
+        memo: Option<String>,
+    ) -> StdResult<u64> {
+        let id = LEDGER_SEQ.may_load(storage)?.unwrap_or_default() + 1;
+        LEDGER_SEQ.save(storage, &id)?;
+        HISTORY.save(storage, (account, id), &LedgerEntry {
+            id,
+            kind,
+            counterparty,
+            amount,
+            height: env.block.height,
+            time: env.block.time,
+            memo,
+        })?;
+        Ok(id)
+    }
+
+    // Retries of an operation already applied are rejected through their client reference
+    fn assert_new_reference(deps: Deps, sender: &Addr, client_ref: Option<&str>) -> Result<(), ContractError> {
+        match client_ref {
+            Some(client_ref) if CLIENT_REFS.has(deps.storage, (sender, client_ref)) => {
+                Err(ContractError::DuplicateReference { client_ref: client_ref.to_string() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The entry id is kept with the account whose ledger holds it, which differs from the sender for operators and spenders
+    fn save_reference(storage: &mut dyn Storage, sender: &Addr, account: &Addr, client_ref: Option<&str>, id: u64) -> StdResult<()> {
+        match client_ref {
+            Some(client_ref) => CLIENT_REFS.save(storage, (sender, client_ref), &(account.clone(), id)),
+            None => Ok(()),
+        }
+    }
+
+    fn add_reference_attributes(mut response: Response, memo: Option<String>, client_ref: Option<String>) -> Response {
+        if let Some(memo) = memo {
+            response = response.add_attribute("memo", memo);
+        }
+        if let Some(client_ref) = client_ref {
+            response = response.add_attribute("client_ref", client_ref);
+        }
+        response
+    }
+
+    type SortedFunds = (Vec<(Coin, DenomInfo)>, Vec<Coin>);
+
+    // Splits the coins sent with a deposit into the registered native denoms and the unexpected ones
+    fn sort_funds(deps: Deps, funds: Vec<Coin>) -> StdResult<SortedFunds> {
+        let mut accepted = vec![];
+        let mut unexpected = vec![];
+        for coin in funds {
+            if coin.amount.is_zero() {
+                continue;
+            }
+            match DENOMS.may_load(deps.storage, &coin.denom)? {
+                Some(denom_info) if matches!(denom_info.asset, AssetInfo::Native(_)) => {
+                    accepted.push((coin, denom_info))
+                }
+                _ => unexpected.push(coin),
+            }
+        }
+        Ok((accepted, unexpected))
+    }
+
+    fn assert_unexpected_funds_allowed(config: &Config, unexpected: &[Coin]) -> Result<(), ContractError> {
+        if config.unexpected_funds == UnexpectedFundsPolicy::Strict && !unexpected.is_empty() {
+            return Err(ContractError::UnexpectedFunds {
+                denoms: unexpected.iter().map(|c| c.denom.clone()).collect(),
+            });
+        }
+        Ok(())
+    }
+
+    fn refund_unexpected_funds(response: Response, depositor: &Addr, unexpected: Vec<Coin>) -> Response {
+        if unexpected.is_empty() {
+            return response;
+        }
+        response
+            .add_attribute("refunded", coins_to_string(&unexpected))
+            .add_message(BankMsg::Send {
+                to_address: depositor.to_string(),
+                amount: unexpected,
+            })
+    }
+
+    fn assert_deposit_amount(
+        coin: &Coin,
+        expected: Option<Uint128>,
+        min_credit: Option<Uint128>,
+    ) -> Result<(), ContractError> {
+        if let Some(expected) = expected {
+            if coin.amount != expected {
+                return Err(ContractError::DepositAmountMismatch {
+                    denom: coin.denom.clone(),
+                    expected,
+                    received: coin.amount,
+                });
+            }
+        }
+        if let Some(min_credit) = min_credit {
+            if coin.amount < min_credit {
+                return Err(ContractError::DepositBelowMinCredit {
+                    denom: coin.denom.clone(),
+                    min_credit,
+                    credited: coin.amount,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn send_asset(asset: &AssetInfo, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+        Ok(match asset {
+            AssetInfo::Native(denom) => BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin::new(amount, denom)],
+            }
+            .into(),
+            AssetInfo::Cw20(contract) => WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: recipient.to_string(),
+                    amount,
+                })?,
+                funds: vec![],
+            }
+            .into(),
+        })
+    }
+
+    fn assert_within_cap(denom_info: &DenomInfo, denom: &str, balance: Uint128) -> Result<(), ContractError> {
+        match denom_info.max_per_account {
+            Some(cap) if balance > cap => Err(ContractError::AccountCapExceeded { denom: denom.to_string(), cap }),
+            _ => Ok(()),
+        }
+    }
+
+    fn coins_to_string(coins: &[Coin]) -> String {
+        coins.iter().map(Coin::to_string).collect::<Vec<_>>().join(",")
+    }
+
+    fn assert_admin(config: &Config, sender: &Addr) -> Result<(), ContractError> {
+        match &config.admin {
+            Some(admin) if admin == sender => Ok(()),
+            _ => Err(ContractError::Unauthorized {}),
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(
+    deps: DepsMut,
+    env: Env,
+    msg: SudoMsg,
+) -> Result<Response, ContractError> {
+    let response = match msg {
+        SudoMsg::UpdateAdmin { admin } => {
+            let config = CONFIG.load(deps.storage)?;
+            execute::replace_admin(deps, config, admin)?
+        }
+        SudoMsg::Pause { operations, until } => execute::pause_operations(deps.storage, &env, operations, until, false)?,
+        SudoMsg::Unpause { operations } => execute::unpause_operations(deps.storage, operations)?,
+        SudoMsg::Freeze { address, reason } => execute::freeze_account(deps, &env, address, reason)?,
+        SudoMsg::Unfreeze { address } => execute::unfreeze_account(deps, address)?,
+    };
+    Ok(response.add_attribute("sudo", "true"))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(
+    mut deps: DepsMut,
+    _env: Env,
+    msg: MigrateMsg,
+) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract {
+            expected: CONTRACT_NAME.to_string(),
+            found: stored.contract,
+        });
+    }
+
+    let stored_version: Version = stored.version.parse()?;
+    let new_version: Version = CONTRACT_VERSION.parse()?;
+    if stored_version > new_version {
+        return Err(ContractError::CannotMigrateDowngrade {
+            stored: stored.version,
+            new: CONTRACT_VERSION.to_string(),
+        });
+    }
+
+    migrate::run(deps.branch(), &stored_version, msg)?;
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    Ok(Response::new()
+        .add_attribute("method", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
+
+pub mod migrate {
+    use cosmwasm_std::{Empty, Order};
+    use cw_storage_plus::{Item, Map};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    // Storage migrations, applied in order to every contract stored below the version of the step
+    pub fn run(
+        mut deps: DepsMut,
+        from: &Version,
+        msg: MigrateMsg,
+    ) -> Result<(), ContractError> {
+        if *from < Version::new(0, 2, 0) {
+            v0_2_0(deps.branch(), msg.admin)?;
+        }
+        if *from < Version::new(0, 3, 0) {
+            v0_3_0(deps.branch())?;
+        }
+        if *from < Version::new(0, 4, 0) {
+            v0_4_0(deps.branch())?;
+        }
+        if *from < Version::new(0, 5, 0) {
+            v0_5_0(deps.branch())?;
+        }
+        if *from < Version::new(0, 6, 0) {
+            v0_6_0(deps.branch())?;
+        }
+        Ok(())
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct ConfigV0_1 {
+        pub allowed_denom: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct ConfigV0_2 {
+        pub allowed_denom: String,
+        pub admin: Option<Addr>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct ConfigV0_3 {
+        pub allowed_denoms: Vec<String>,
+        pub admin: Option<Addr>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct ConfigV0_4 {
+        pub admin: Option<Addr>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct DenomInfoV0_4 {
+        pub enabled: bool,
+        pub min_deposit: Option<Uint128>,
+        pub max_per_account: Option<Uint128>,
+        pub label: Option<String>,
+    }
+
+    pub(crate) const CONFIG_V0_1: Item<ConfigV0_1> = Item::new("state");
+    pub(crate) const CONFIG_V0_2: Item<ConfigV0_2> = Item::new("state");
+    pub(crate) const CONFIG_V0_3: Item<ConfigV0_3> = Item::new("state");
+    pub(crate) const CONFIG_V0_4: Item<ConfigV0_4> = Item::new("state");
+    pub(crate) const BALANCES_V0_2: Map<Addr, Uint128> = Map::new("balances");
+    // Primary namespaces of BALANCES and TOTAL_DEPOSITS, written without snapshots
+    pub(crate) const BALANCES_V0_5: Map<(&Addr, &str), Uint128> = Map::new("deposits");
+    pub(crate) const TOTAL_DEPOSITS_V0_6: Map<&str, Uint128> = Map::new("total_deposits");
+    pub(crate) const HOLDERS_V0_6: Map<(&str, &Addr), Empty> = Map::new("holders");
+    pub(crate) const DENOMS_V0_4: Map<&str, DenomInfoV0_4> = Map::new("denoms");
+
+    // 0.2.0 added the admin role to the config
+    fn v0_2_0(deps: DepsMut, admin: Option<String>) -> Result<(), ContractError> {
+        let old = CONFIG_V0_1.load(deps.storage)?;
+        let admin = admin.map(|admin| deps.api.addr_validate(&admin)).transpose()?;
+        CONFIG_V0_2.save(deps.storage, &ConfigV0_2 {
+            allowed_denom: old.allowed_denom,
+            admin,
+        })?;
+        Ok(())
+    }
+
+    // 0.3.0 moved from a single allowed denom to per-denom balances
+    fn v0_3_0(deps: DepsMut) -> Result<(), ContractError> {
+        let old = CONFIG_V0_2.load(deps.storage)?;
+        let balances = BALANCES_V0_2
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for (owner, balance) in balances {
+            BALANCES_V0_5.save(deps.storage, (&owner, &old.allowed_denom), &balance)?;
+            BALANCES_V0_2.remove(deps.storage, owner);
+        }
+        CONFIG_V0_3.save(deps.storage, &ConfigV0_3 {
+            allowed_denoms: vec![old.allowed_denom],
+            admin: old.admin,
+        })?;
+        Ok(())
+    }
+
+    // 0.4.0 moved the allowed denoms into the DENOMS registry
+    fn v0_4_0(deps: DepsMut) -> Result<(), ContractError> {
+        let old = CONFIG_V0_3.load(deps.storage)?;
+        let default = DenomInfoV0_4 { enabled: true, min_deposit: None, max_per_account: None, label: None };
+        for denom in &old.allowed_denoms {
+            DENOMS_V0_4.save(deps.storage, denom, &default)?;
+        }
+        CONFIG_V0_4.save(deps.storage, &ConfigV0_4 { admin: old.admin })?;
+        Ok(())
+    }
+
+    // 0.5.0 added CW20 tokens, so registry entries record the kind of asset
+    fn v0_5_0(deps: DepsMut) -> Result<(), ContractError> {
+        let denoms = DENOMS_V0_4
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for (denom, old) in denoms {
+            DENOMS.save(deps.storage, &denom, &DenomInfo {
+                asset: AssetInfo::Native(denom.clone()),
+                enabled: old.enabled,
+                min_deposit: old.min_deposit,
+                max_per_account: old.max_per_account,
+                label: old.label,
+            })?;
+        }
+        Ok(())
+    }
+
+    // 0.6.0 stores the total deposit of each denom instead of summing the balances on query,
+    // and indexes the owners of each denom
+    fn v0_6_0(deps: DepsMut) -> Result<(), ContractError> {
+        let balances = BALANCES_V0_5
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for ((owner, denom), balance) in balances {
+            if !balance.is_zero() {
+                HOLDERS_V0_6.save(deps.storage, (&denom, &owner), &Empty {})?;
+            }
+            TOTAL_DEPOSITS_V0_6.update(deps.storage, &denom, |total| -> StdResult<_> {
+                Ok(total.unwrap_or_default().checked_add(balance)?)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(
+    deps: Deps,
+    env: Env, 
+    msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::GetState {} => to_json_binary(&query::state(deps)?),
+        QueryMsg::GetDeposit {owner, denom} => to_json_binary(&query::deposit(deps, owner, denom)?),
+        QueryMsg::GetDeposits {owners, denom} => to_json_binary(&query::deposits(deps, owners, denom)?),
+        QueryMsg::GetAccountDeposits {owner} => to_json_binary(&query::account_deposits(deps, owner)?),
+        QueryMsg::GetAllDeposit {denom, start_after, limit, order} => to_json_binary(&query::all_deposits(deps, denom, start_after, limit, order)?),
+        QueryMsg::GetTotalDeposit {denom} => to_json_binary(&query::totaldeposit(deps, denom)?),
+        QueryMsg::GetDepositAtHeight {owner, denom, height} => to_json_binary(&query::deposit_at_height(deps, owner, denom, height)?),
+        QueryMsg::GetTotalDepositAtHeight {denom, height} => to_json_binary(&query::totaldeposit_at_height(deps, denom, height)?),
+        QueryMsg::GetHistory {address, start_after, limit} => to_json_binary(&query::history(deps, address, start_after, limit)?),
+        QueryMsg::GetClientRef {sender, client_ref} => to_json_binary(&query::client_ref(deps, sender, client_ref)?),
+        QueryMsg::ListOperators {owner, include_expired, start_after, limit} => to_json_binary(&query::list_operators(deps, env, owner, include_expired, start_after, limit)?),
+        QueryMsg::GetAllowance {owner, spender, denom} => to_json_binary(&query::allowance(deps, owner, spender, denom)?),
+        QueryMsg::AllAllowances {owner, start_after, limit} => to_json_binary(&query::all_allowances(deps, owner, start_after, limit)?),
+        QueryMsg::AllSpenderAllowances {spender, start_after, limit} => to_json_binary(&query::all_spender_allowances(deps, spender, start_after, limit)?),
+        QueryMsg::Balance {address} => to_json_binary(&query::cw20_balance(deps, address)?),
+        QueryMsg::TokenInfo {} => to_json_binary(&query::cw20_token_info(deps)?),
+        QueryMsg::AllAccounts {start_after, limit} => to_json_binary(&query::cw20_all_accounts(deps, start_after, limit)?),
+        QueryMsg::GetSolvency {} => to_json_binary(&query::solvency(deps, &env)?),
+        QueryMsg::GetPauseStatus {} => to_json_binary(&query::pause_status(deps, &env)?),
+        QueryMsg::ListFrozen {start_after, limit} => to_json_binary(&query::list_frozen(deps, start_after, limit)?),
+        QueryMsg::AuditTotalDeposit {denom, start_after, limit} => to_json_binary(&query::audit_totaldeposit(deps, denom, start_after, limit)?),
+        QueryMsg::GetOwnership {} => to_json_binary(&query::ownership(deps)?),
+        QueryMsg::ListDenoms { start_after, limit } => to_json_binary(&query::list_denoms(deps, start_after, limit)?),
+        QueryMsg::DenomInfo { denom } => to_json_binary(&query::denom_info(deps, denom)?),
+        }
+    }
+
+pub mod query {
+
+    use cosmwasm_std::{Coin, Order, StdError};
+    use cw_storage_plus::{Bound, PrefixBound};
+
+    use crate::msg::{DepositEntry, GetClientRefResponse, GetDepositAtHeightResponse, GetHistoryResponse, GetTotalDepositAtHeightResponse};
+    use crate::msg::{AllAllowancesResponse, AllSpenderAllowancesResponse, AllowanceInfo, GetAllowanceResponse, SpenderAllowanceInfo};
+    use crate::msg::{DenomSolvency, FrozenAccount, GetPauseStatusResponse, GetSolvencyResponse, ListFrozenResponse, ListOperatorsResponse, Operation, OperationStatus, OperatorInfo};
+    use crate::state::TokenConfig;
+    use cw20::{AllAccountsResponse, BalanceResponse, Cw20QueryMsg, TokenInfoResponse};
+    use crate::state::{ALLOWANCES, ALLOWANCES_SPENDER, CLIENT_REFS, FROZEN, HISTORY, HOLDERS, OPERATORS, PAUSED};
+
+    use super::*;
+
+    const DEFAULT_LIMIT: u32 = 10;
+    const MAX_LIMIT: u32 = 30;
+
+    pub fn state(
+        deps: Deps) -> StdResult<GetStateResponse> {
+        let config = CONFIG.load(deps.storage)?;
+        let allowed_denoms = DENOMS
+            .range(deps.storage, None, None, Order::Ascending)
+            .filter_map(|item| match item {
+                Ok((denom, denom_info)) if denom_info.enabled => Some(Ok(denom)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(GetStateResponse {allowed_denoms, admin: config.admin, unexpected_funds: config.unexpected_funds, guardian: config.guardian, shutdown: config.shutdown, membership: config.membership})
+    }
+
+    pub fn list_denoms(
+        deps: Deps,
+        start_after: Option<String>,
+        limit: Option<u32>) -> StdResult<ListDenomsResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.as_deref().map(Bound::exclusive);
+        let denoms = DENOMS
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let (denom, denom_info) = item?;
+                Ok(denom_info_response(denom, denom_info))
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(ListDenomsResponse { denoms })
+    }
+
+    pub fn denom_info(
+        deps: Deps,
+        denom: String) -> StdResult<DenomInfoResponse> {
+        let denom_info = DENOMS.load(deps.storage, &denom)?;
+        Ok(denom_info_response(denom, denom_info))
+    }
+
+    fn denom_info_response(denom: String, denom_info: DenomInfo) -> DenomInfoResponse {
+        DenomInfoResponse {
+            denom,
+            asset: denom_info.asset,
+            enabled: denom_info.enabled,
+            min_deposit: denom_info.min_deposit,
+            max_per_account: denom_info.max_per_account,
+            label: denom_info.label,
+        }
+    }
+
+    pub fn ownership(deps: Deps) -> StdResult<GetOwnershipResponse> {
+        let config = CONFIG.load(deps.storage)?;
+        let pending = PENDING_OWNER.may_load(deps.storage)?;
+        Ok(GetOwnershipResponse {
+            owner: config.admin,
+            pending_owner: pending.as_ref().map(|p| p.owner.clone()),
+            pending_expiry: pending.map(|p| p.expiry),
+        })
+    }
+
+    pub fn deposit(
+        deps: Deps, 
+        owner: String,
+        denom: String) -> StdResult<GetDepositResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let balance = BALANCES.may_load(deps.storage, (&owner, &denom))?;
+        Ok(GetDepositResponse {address: owner, denom, deposit: balance.unwrap_or_default(), exists: balance.is_some() })
+    }
+
+    pub fn deposits(
+        deps: Deps,
+        owners: Vec<String>,
+        denom: String) -> StdResult<GetDepositsResponse> {
+        if owners.len() > MAX_LIMIT as usize {
+            return Err(StdError::generic_err(format!("Cannot query more than {} owners at once", MAX_LIMIT)));
+        }
+        let deposits = owners
+            .into_iter()
+            .map(|owner| deposit(deps, owner, denom.clone()))
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(GetDepositsResponse { deposits })
+    }
+
+    pub fn account_deposits(
+        deps: Deps,
+        owner: String) -> StdResult<GetAccountDepositsResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let deposits = BALANCES
+            .prefix(&owner)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (denom, amount) = item?;
+                Ok(Coin { denom, amount })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(GetAccountDepositsResponse {address: owner, deposits })
+    }
+
+    pub fn all_deposits(
+        deps: Deps,
+        denom: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+        order: Option<Order>) -> StdResult<GetAllDepositResponse> {
+        // The limit counts owners, so the deposits of one owner are never split across pages.
+        // A page holds at least one owner, an empty page would read as the last one
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+        let order = order.unwrap_or(Order::Ascending);
+        let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+        let bound = start_after.as_ref().map(PrefixBound::exclusive);
+        let (min, max) = match order {
+            Order::Ascending => (bound, None),
+            Order::Descending => (None, bound),
+        };
+
+        // A single denom is read through the index, so only its holders are walked
+        if let Some(denom) = denom {
+            let (min, max) = match order {
+                Order::Ascending => (start_after.as_ref().map(Bound::exclusive), None),
+                Order::Descending => (None, start_after.as_ref().map(Bound::exclusive)),
+            };
+            let mut deposits = HOLDERS
+                .prefix(&denom)
+                .keys(deps.storage, min, max, order)
+                .take(limit + 1)
+                .map(|address| {
+                    let address = address?;
+                    let balance = BALANCES.load(deps.storage, (&address, &denom))?;
+                    Ok(DepositEntry { address, denom: denom.clone(), totaldeposit: balance })
+                })
+                .collect::<StdResult<Vec<_>>>()?;
+            let next_key = (deposits.len() > limit).then(|| {
+                deposits.truncate(limit);
+                deposits[limit - 1].address.to_string()
+            });
+            return Ok(GetAllDepositResponse { deposits, next_key });
+        }
+
+        let mut deposits = vec![];
+        let mut owners = 0;
+        let mut next_key = None;
+        for item in BALANCES.prefix_range(deps.storage, min, max, order) {
+            let ((address, item_denom), balance) = item?;
+            // Emptied deposits stay stored, they are left out as in the listing of a single denom
+            if balance.is_zero() {
+                continue;
+            }
+            let last: Option<&Addr> = deposits.last().map(|entry: &DepositEntry| &entry.address);
+            if last != Some(&address) {
+                if owners == limit {
+                    next_key = last.map(Addr::to_string);
+                    break;
+                }
+                owners += 1;
+            }
+            deposits.push(DepositEntry {
+                address,
+                denom: item_denom,
+                totaldeposit: balance,
+            });
+        }
+
+        Ok(GetAllDepositResponse { deposits, next_key })
+    }
+
+    pub fn totaldeposit(deps: Deps, denom: String) -> StdResult<GetTotalDepositResponse> {
+        let total_deposit = TOTAL_DEPOSITS.may_load(deps.storage, &denom)?.unwrap_or_default();
+        Ok(GetTotalDepositResponse { denom, totaldeposit: total_deposit })
+    }
+
+    pub fn deposit_at_height(
+        deps: Deps,
+        owner: String,
+        denom: String,
+        height: u64) -> StdResult<GetDepositAtHeightResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let balance = BALANCES.may_load_at_height(deps.storage, (&owner, &denom), height)?;
+        Ok(GetDepositAtHeightResponse { address: owner, denom, height, deposit: balance.unwrap_or_default() })
+    }
+
+    pub fn totaldeposit_at_height(
+        deps: Deps,
+        denom: String,
+        height: u64) -> StdResult<GetTotalDepositAtHeightResponse> {
+        let total_deposit = TOTAL_DEPOSITS.may_load_at_height(deps.storage, &denom, height)?.unwrap_or_default();
+        Ok(GetTotalDepositAtHeightResponse { denom, height, totaldeposit: total_deposit })
+    }
+
+    pub fn history(
+        deps: Deps,
+        address: String,
+        start_after: Option<u64>,
+        limit: Option<u32>) -> StdResult<GetHistoryResponse> {
+        let address = deps.api.addr_validate(&address)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start = start_after.map(Bound::exclusive);
+        let entries = HISTORY
+            .prefix(&address)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, entry)| entry))
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(GetHistoryResponse { entries })
+    }
+
+    pub fn client_ref(
+        deps: Deps,
+        sender: String,
+        client_ref: String) -> StdResult<GetClientRefResponse> {
+        let sender = deps.api.addr_validate(&sender)?;
+        let entry = match CLIENT_REFS.may_load(deps.storage, (&sender, &client_ref))? {
+            Some((account, id)) => HISTORY.may_load(deps.storage, (&account, id))?,
+            None => None,
+        };
+        Ok(GetClientRefResponse { sender, client_ref, entry })
+    }
+
+    pub fn list_operators(
+        deps: Deps,
+        env: Env,
+        owner: String,
+        include_expired: Option<bool>,
+        start_after: Option<String>,
+        limit: Option<u32>) -> StdResult<ListOperatorsResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+        let start = start_after.as_ref().map(Bound::exclusive);
+        let include_expired = include_expired.unwrap_or(false);
+        let operators = OPERATORS
+            .prefix(&owner)
+            .range(deps.storage, start, None, Order::Ascending)
+            .filter(|item| match item {
+                Ok((_, expires)) => include_expired || !expires.is_expired(&env.block),
+                Err(_) => true,
+            })
+            .take(limit)
+            .map(|item| {
+                let (operator, expires) = item?;
+                Ok(OperatorInfo { operator, expires })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(ListOperatorsResponse { operators })
+    }
+
+    pub fn allowance(
+        deps: Deps,
+        owner: String,
+        spender: String,
+        denom: String) -> StdResult<GetAllowanceResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let spender = deps.api.addr_validate(&spender)?;
+        let allowance = ALLOWANCES
+            .may_load(deps.storage, (&owner, &spender, &denom))?
+            .unwrap_or_default();
+        Ok(GetAllowanceResponse { allowance: allowance.amount, expires: allowance.expires })
+    }
+
+    pub fn all_allowances(
+        deps: Deps,
+        owner: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>) -> StdResult<AllAllowancesResponse> {
+        let owner = deps.api.addr_validate(&owner)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after
+            .map(|(spender, denom)| deps.api.addr_validate(&spender).map(|spender| (spender, denom)))
+            .transpose()?;
+        let start = start_after.as_ref().map(|(spender, denom)| Bound::exclusive((spender, denom.as_str())));
+        let allowances = ALLOWANCES
+            .sub_prefix(&owner)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let ((spender, denom), allowance) = item?;
+                Ok(AllowanceInfo { spender, denom, allowance: allowance.amount, expires: allowance.expires })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(AllAllowancesResponse { allowances })
+    }
+
+    pub fn all_spender_allowances(
+        deps: Deps,
+        spender: String,
+        start_after: Option<(String, String)>,
+        limit: Option<u32>) -> StdResult<AllSpenderAllowancesResponse> {
+        let spender = deps.api.addr_validate(&spender)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after
+            .map(|(owner, denom)| deps.api.addr_validate(&owner).map(|owner| (owner, denom)))
+            .transpose()?;
+        let start = start_after.as_ref().map(|(owner, denom)| Bound::exclusive((owner, denom.as_str())));
+        let allowances = ALLOWANCES_SPENDER
+            .sub_prefix(&spender)
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let ((owner, denom), allowance) = item?;
+                Ok(SpenderAllowanceInfo { owner, denom, allowance: allowance.amount, expires: allowance.expires })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(AllSpenderAllowancesResponse { allowances })
+    }
+
+    fn load_token(deps: Deps) -> StdResult<TokenConfig> {
+        TOKEN
+            .may_load(deps.storage)?
+            .ok_or_else(|| StdError::generic_err("No denom is exposed as a CW20 token"))
+    }
+
+    pub fn cw20_balance(deps: Deps, address: String) -> StdResult<BalanceResponse> {
+        let token = load_token(deps)?;
+        let address = deps.api.addr_validate(&address)?;
+        let balance = BALANCES.may_load(deps.storage, (&address, &token.denom))?.unwrap_or_default();
+        Ok(BalanceResponse { balance })
+    }
+
+    pub fn cw20_token_info(deps: Deps) -> StdResult<TokenInfoResponse> {
+        let token = load_token(deps)?;
+        let denom_info = DENOMS.load(deps.storage, &token.denom)?;
+
+        // Metadata of the underlying asset, the denom itself when the bank has none
+        let (name, symbol, decimals) = match &denom_info.asset {
+            AssetInfo::Native(denom) => match deps.querier.query_denom_metadata(denom) {
+                Ok(metadata) => {
+                    let decimals = metadata.denom_units
+                        .iter()
+                        .find(|unit| unit.denom == metadata.display)
+                        .and_then(|unit| u8::try_from(unit.exponent).ok())
+                        .unwrap_or_default();
+                    (metadata.name, metadata.symbol, decimals)
+                }
+                Err(_) => (denom.clone(), denom.clone(), 0),
+            },
+            AssetInfo::Cw20(contract) => {
+                let info: TokenInfoResponse = deps.querier.query_wasm_smart(contract, &Cw20QueryMsg::TokenInfo {})?;
+                (info.name, info.symbol, info.decimals)
+            }
+        };
+        let total_supply = TOTAL_DEPOSITS.may_load(deps.storage, &token.denom)?.unwrap_or_default();
+
+        Ok(TokenInfoResponse {
+            name: token.name.unwrap_or(name),
+            symbol: token.symbol.unwrap_or(symbol),
+            decimals: token.decimals.unwrap_or(decimals),
+            total_supply,
+        })
+    }
+
+    pub fn cw20_all_accounts(
+        deps: Deps,
+        start_after: Option<String>,
+        limit: Option<u32>) -> StdResult<AllAccountsResponse> {
+        let token = load_token(deps)?;
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+        let start = start_after.as_ref().map(Bound::exclusive);
+        let accounts = HOLDERS
+            .prefix(&token.denom)
+            .keys(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|address| address.map(String::from))
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(AllAccountsResponse { accounts })
+    }
+
+    // Balance of the contract in the bank or in the CW20 token
+    pub fn held_balance(deps: Deps, env: &Env, asset: &AssetInfo) -> StdResult<Uint128> {
+        Ok(match asset {
+            AssetInfo::Native(denom) => deps.querier.query_balance(&env.contract.address, denom)?.amount,
+            AssetInfo::Cw20(contract) => {
+                let balance: BalanceResponse = deps.querier.query_wasm_smart(contract, &Cw20QueryMsg::Balance {
+                    address: env.contract.address.to_string(),
+                })?;
+                balance.balance
+            }
+        })
+    }
+
+    pub fn solvency(deps: Deps, env: &Env) -> StdResult<GetSolvencyResponse> {
+        let denoms = DENOMS
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (denom, denom_info) = item?;
+                let held = held_balance(deps, env, &denom_info.asset)?;
+                let deposits = TOTAL_DEPOSITS.may_load(deps.storage, &denom)?.unwrap_or_default();
+                Ok(DenomSolvency {
+                    denom,
+                    held,
+                    deposits,
+                    surplus: held.saturating_sub(deposits),
+                    deficit: deposits.saturating_sub(held),
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        let solvent = denoms.iter().all(|denom| denom.deficit.is_zero());
+        Ok(GetSolvencyResponse { denoms, solvent })
+    }
+
+    pub fn pause_status(deps: Deps, env: &Env) -> StdResult<GetPauseStatusResponse> {
+        let operations = Operation::ALL
+            .into_iter()
+            .map(|operation| {
+                // A pause past its expiry is over even though it is still stored
+                let until = PAUSED
+                    .may_load(deps.storage, operation.key())?
+                    .filter(|until| !until.is_expired(&env.block));
+                Ok(OperationStatus { operation, paused: until.is_some(), until })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(GetPauseStatusResponse { operations })
+    }
+
+    pub fn list_frozen(deps: Deps, start_after: Option<String>, limit: Option<u32>) -> StdResult<ListFrozenResponse> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+        let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+        let start = start_after.as_ref().map(Bound::exclusive);
+        let accounts = FROZEN
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| {
+                let (address, info) = item?;
+                Ok(FrozenAccount { address, reason: info.reason, since: info.since })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        Ok(ListFrozenResponse { accounts })
+    }
+
+    pub fn audit_totaldeposit(
+        deps: Deps,
+        denom: String,
+        start_after: Option<String>,
+        limit: Option<u32>) -> StdResult<AuditTotalDepositResponse> {
+        let stored = TOTAL_DEPOSITS.may_load(deps.storage, &denom)?.unwrap_or_default();
+        // The limit counts the owners walked, whatever denoms they hold
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+        let start_after = start_after.map(|addr| deps.api.addr_validate(&addr)).transpose()?;
+        let bound = start_after.as_ref().map(PrefixBound::exclusive);
+
+        let mut computed = Uint128::zero();
+        let mut last: Option<Addr> = None;
+        let mut owners = 0;
+        let mut next_key = None;
+        for item in BALANCES.prefix_range(deps.storage, bound, None, Order::Ascending) {
+            let ((address, item_denom), balance) = item?;
+            if last.as_ref() != Some(&address) {
+                if owners == limit {
+                    next_key = last.map(|addr| addr.to_string());
+                    break;
+                }
+                owners += 1;
+                last = Some(address);
+            }
+            if item_denom == denom {
+                computed = computed.checked_add(balance)?;
+            }
+        }
+        let consistent = (start_after.is_none() && next_key.is_none()).then_some(stored == computed);
+        Ok(AuditTotalDepositResponse { denom, stored, computed, consistent, next_key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{message_info, mock_dependencies, mock_env};
+    use cosmwasm_std::{coins, from_json, BankMsg, Coin, DenomMetadata, DenomUnit, Order};
+    use crate::msg::{AllAllowancesResponse, AllSpenderAllowancesResponse, DepositEntry, GetAllowanceResponse, ListFrozenResponse, GetClientRefResponse, GetPauseStatusResponse, GetSolvencyResponse, Operation, ListOperatorsResponse, GetDepositAtHeightResponse, GetHistoryResponse, GetTotalDepositAtHeightResponse, LedgerKind, UnexpectedFundsPolicy};
+    use crate::state::ALLOWANCES_SPENDER;
+    use cw_utils::Expiration;
+
+
+    // Istantiate
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+
+        // we can just call .unwrap() to assert this was a success
+        let res = instantiate(deps.as_mut(), mock_env(), info, msg);
+        assert!(res.is_ok());
+        assert_eq!(0, res.unwrap().messages.len());
+
+        // query the state
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(vec!["tsy".to_string()], value.allowed_denoms);
+    }
+
+    // Test deposit successful
+    #[test]
+    fn test_deposit_successfully() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 0);
+
+        // Check the balance in the storage
+        let balance = BALANCES.load(&deps.storage, (&deps.api.addr_make("depositor"), "tsy")).unwrap();
+        assert_eq!(balance, Uint128::new(1000));
+    
+        // Check if the correct event is emitted
+        // let events = res.events;
+        // assert_eq!(events.len(), 1);
+        // assert_eq!(events[0].ty, "deposit");
+        // assert_eq!(events[0].attributes.len(), 2);
+        // assert_eq!(events[0].attributes[0].key, "sender");
+        // assert_eq!(events[0].attributes[0].value, "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0");
+        // assert_eq!(events[0].attributes[1].key, "amount");
+        // assert_eq!(events[0].attributes[1].value, "2tsy");
+    }
+
+    // Test deposit error 0
+    #[test]
+    fn test_deposit_error_0_amount() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(0, "tsy".to_string()));
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        // assert!(res.is_err(),"Must return Invalid Deposit Amount error");
+        // let err = res.unwrap_err();
+        // assert_eq!(err, ContractError::InvalidDepositAmount{}, "Must return Invalid Deposit Amount error");
+        match res { 
+            Err(ContractError::InvalidDepositAmount {}) => {}
+            _ => panic!("Must return Invalid Deposit Amount error"),
+        }
+    }
+    // Test deposit error 0 parte 2
+    #[test]
+    fn test_deposit_error_0_amount_not_matching_allowed_denom() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "thi".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::InvalidDepositAmount {}) => {}
+            _ => panic!("Must return Invalid Deposit Amount error"),
+        }
+    }
+
+    // Test transfer successfully
+    #[test]
+    fn test_transfer_successfully() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("sender1"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: deps.api.addr_make("sender2").to_string(), amount: Uint128::new(2), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let info = message_info(&deps.api.addr_make("sender1"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let res_unwrapped = res.unwrap();
+        assert_eq!(res_unwrapped.messages.len(), 0);
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: deps.api.addr_make("sender2").to_string(), denom: "tsy".to_string() }).unwrap();
+        let value: GetDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposit, Uint128::new(2));
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: deps.api.addr_make("sender1").to_string(), denom: "tsy".to_string() }).unwrap();
+        let value: GetDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposit, Uint128::new(998));
     }
 
     // Test transfer error fund not empty
     #[test]
-    fn test_transfer_error_fund_not_empty() {
+    fn test_transfer_error_fund_not_empty() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: deps.api.addr_make("receiver").to_string(), amount: Uint128::new(2), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::NoEmptyFunds {}) => {}
+            _ => panic!("Must return Fund Not Empty error"),
+        }
+    }
+
+    // Test transfer error invalid address
+    #[test]
+    fn test_transfer_error_invalid_address() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: "not-a-valid-address".to_string(), amount: Uint128::new(2), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::Std(_)) => {}
+            _ => panic!("Must return Invalid Address error"),
+        }
+    }
+
+    // Test transfer error 0
+    #[test]
+    fn test_transfer_error_0_transfer() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: deps.api.addr_make("receiver").to_string(), amount: Uint128::new(0), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::InvalidTransferAmount {}) => {}
+            _ => panic!("Must return Invalid Transfer Amount error"),
+        }
+    }
+
+    // Test transfer error sender no deposit
+    #[test]
+    fn test_transfer_error_sender_no_deposit() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: deps.api.addr_make("receiver").to_string(), amount: Uint128::new(2), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let info = message_info(&deps.api.addr_make("receiver"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::AddressHasNotDeposit {}) => {}
+            _ => panic!("Must return Address Has No Deposit error"),
+        }
+    }
+
+    // Test transfer error transfer exceed deposit
+    #[test]
+    fn test_transfer_error_transfer_exceed_deposit() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Transfer {receiver: deps.api.addr_make("receiver").to_string(), amount: Uint128::new(10000), denom: "tsy".to_string(), owner: None, memo: None, client_ref: None};
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::TransferFundsExceedsBalance {}) => {}
+            _ => panic!("Must return Transfer Exceed Balance error"),
+        }
+    }
+
+    // Test withdraw successfully
+    #[test]
+    fn test_withdraw_successfully() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(2), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.iter().any(|a| a.key == "amount" && a.value == "2tsy"));
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: deps.api.addr_make("depositor").to_string(), denom: "tsy".to_string() }).unwrap();
+        let value: GetDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposit, Uint128::new(998));
+    }
+
+    // Test withdraw error fund not empty
+    #[test]
+    fn test_withdraw_error_fund_not_empty() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(2), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::NoEmptyFunds {}) => {}
+            _ => panic!("Must return Fund Not Empty error"),
+        }
+    }
+
+    // Test withdraw error 0
+    #[test]
+    fn test_withdraw_error_0_withdraw() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(0), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::InvalidWithdrawAmount {}) => {}
+            _ => panic!("Must return Invalid Withdraw Amount error"),
+        }
+    }
+
+    // Test withdraw error receiver no deposit
+    #[test]
+    fn test_withdraw_error_sender_no_deposit() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(2), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let info = message_info(&deps.api.addr_make("other"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::AddressHasNotDeposit {}) => {}
+            _ => panic!("Must return Address Has No Deposit error"),
+        }
+    }
+
+    // Test withdraw error withdraw exceed deposit
+    #[test]
+    fn test_withdraw_error_withdraw_exceed_deposit() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &coins(1000, "tsy".to_string()));
+        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(10000), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let info = message_info(&deps.api.addr_make("depositor"), &[]);
+        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        match res {
+            Err(ContractError::WithdrawFundsExceedsBalance {}) => {}
+            _ => panic!("Must return Withdraw Exceed Balance error"),
+        }
+    }
+
+    // Test instantiate defaults the admin to the sender
+    #[test]
+    fn test_instantiate_admin() {
+        let mut deps = mock_dependencies();
+        let creator = deps.api.addr_make("creator");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&creator, &[]), msg).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.admin, Some(creator.clone()));
+
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: Some(admin.to_string()), unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&creator, &[]), msg).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.admin, Some(admin));
+    }
+
+    // Test denom registry management
+    #[test]
+    fn test_denom_registry() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let params = DenomParams { min_deposit: Some(Uint128::new(10)), max_per_account: None, label: Some("Thi".to_string()) };
+        let msg = ExecuteMsg::AddDenom { asset: AssetInfo::Native("thi".to_string()), params };
+        let other = deps.api.addr_make("other");
+        let res = execute(deps.as_mut(), mock_env(), message_info(&other, &[]), msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg);
+        match res {
+            Err(ContractError::DenomAlreadyRegistered { .. }) => {}
+            _ => panic!("Must return Denom Already Registered error"),
+        }
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::ListDenoms { start_after: None, limit: None }).unwrap();
+        let value: ListDenomsResponse = from_json(&res_q).unwrap();
+        let denoms: Vec<String> = value.denoms.into_iter().map(|d| d.denom).collect();
+        assert_eq!(denoms, vec!["thi".to_string(), "tsy".to_string()]);
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::DenomInfo { denom: "thi".to_string() }).unwrap();
+        let value: DenomInfoResponse = from_json(&res_q).unwrap();
+        assert!(value.enabled);
+        assert_eq!(value.min_deposit, Some(Uint128::new(10)));
+        assert_eq!(value.label, Some("Thi".to_string()));
+
+        // A denom cannot be removed once deposits exist
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &coins(10, "thi")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let msg = ExecuteMsg::RemoveDenom { denom: "thi".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg);
+        match res {
+            Err(ContractError::DepositsOutstanding {}) => {}
+            _ => panic!("Must return Deposits Outstanding error"),
+        }
+
+        let msg = ExecuteMsg::RemoveDenom { denom: "tsy".to_string() };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.allowed_denoms, vec!["thi".to_string()]);
+    }
+
+    // Test per-denom parameters are enforced on deposit
+    #[test]
+    fn test_denom_parameters() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let depositor = deps.api.addr_make("depositor");
+        let msg = InstantiateMsg { allowed_denoms: vec![], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+        let params = DenomParams { min_deposit: Some(Uint128::new(10)), max_per_account: Some(Uint128::new(100)), label: None };
+        let msg = ExecuteMsg::AddDenom { asset: AssetInfo::Native("tsy".to_string()), params };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(5, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::DepositBelowMinimum { .. }) => {}
+            _ => panic!("Must return Deposit Below Minimum error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::AccountCapExceeded { .. }) => {}
+            _ => panic!("Must return Account Cap Exceeded error"),
+        }
+
+        // Disabled denoms can still be withdrawn
+        let msg = ExecuteMsg::DisableDenom { denom: "tsy".to_string() };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(50, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::DenomDisabled { .. }) => {}
+            _ => panic!("Must return Denom Disabled error"),
+        }
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(100), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 1);
+    }
+
+    // Test admin handover and renounce
+    #[test]
+    fn test_update_and_renounce_admin() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let new_admin = deps.api.addr_make("new_admin");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateAdmin { admin: new_admin.to_string() };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        // The previous admin lost its rights
+        let res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), ExecuteMsg::RenounceAdmin {});
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&new_admin, &[]), ExecuteMsg::RenounceAdmin {}).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.admin, None);
+
+        let msg = ExecuteMsg::DisableDenom { denom: "tsy".to_string() };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&new_admin, &[]), msg);
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+    }
+
+    // Test two-step ownership transfer
+    #[test]
+    fn test_ownership_transfer() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let new_owner = deps.api.addr_make("new_owner");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let res = execute(deps.as_mut(), mock_env(), message_info(&new_owner, &[]), ExecuteMsg::AcceptOwnership {});
+        match res {
+            Err(ContractError::NoPendingOwner {}) => {}
+            _ => panic!("Must return No Pending Owner error"),
+        }
+
+        let msg = ExecuteMsg::ProposeNewOwner { owner: new_owner.to_string(), expiry: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&new_owner, &[]), msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetOwnership {}).unwrap();
+        let value: GetOwnershipResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.owner, Some(admin.clone()));
+        assert_eq!(value.pending_owner, Some(new_owner.clone()));
+
+        // Only the proposed owner can accept
+        let res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), ExecuteMsg::AcceptOwnership {});
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&new_owner, &[]), ExecuteMsg::AcceptOwnership {}).unwrap();
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetOwnership {}).unwrap();
+        let value: GetOwnershipResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.owner, Some(new_owner));
+        assert_eq!(value.pending_owner, None);
+        assert_eq!(value.pending_expiry, None);
+    }
+
+    // Test expired and cancelled ownership offers
+    #[test]
+    fn test_ownership_transfer_expired_and_cancelled() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        let new_owner = deps.api.addr_make("new_owner");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let env = mock_env();
+        let msg = ExecuteMsg::ProposeNewOwner { owner: new_owner.to_string(), expiry: Some(Expiration::AtHeight(env.block.height)) };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg);
+        match res {
+            Err(ContractError::InvalidExpiry {}) => {}
+            _ => panic!("Must return Invalid Expiry error"),
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let msg = ExecuteMsg::ProposeNewOwner { owner: new_owner.to_string(), expiry: Some(Expiration::AtHeight(env.block.height + 10)) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
 
-        let msg = ExecuteMsg::Transfer {receiver: "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1".to_string(), amount: Uint128::new(2),};
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let mut later = env.clone();
+        later.block.height += 10;
+        let res = execute(deps.as_mut(), later, message_info(&new_owner, &[]), ExecuteMsg::AcceptOwnership {});
         match res {
-            Err(ContractError::NoEmptyFunds {}) => {}
-            _ => panic!("Must return Fund Not Empty error"),
+            Err(ContractError::OwnershipProposalExpired {}) => {}
+            _ => panic!("Must return Ownership Proposal Expired error"),
+        }
+
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::CancelOwnershipTransfer {}).unwrap();
+        let res = execute(deps.as_mut(), env, message_info(&new_owner, &[]), ExecuteMsg::AcceptOwnership {});
+        match res {
+            Err(ContractError::NoPendingOwner {}) => {}
+            _ => panic!("Must return No Pending Owner error"),
         }
     }
 
-    // Test transfer error invalid address
+    // Test migration from the first release
     #[test]
-    fn test_transfer_error_invalid_address() {
+    fn test_migrate_from_v0_1() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.1.0").unwrap();
+        migrate::CONFIG_V0_1.save(deps.as_mut().storage, &migrate::ConfigV0_1 { allowed_denom: "tsy".to_string() }).unwrap();
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let msg = MigrateMsg { admin: Some(admin.to_string()) };
+        let _res = migrate(deps.as_mut(), mock_env(), msg).unwrap();
 
-        let msg = ExecuteMsg::Transfer {receiver: "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1".to_string(), amount: Uint128::new(2)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.allowed_denoms, vec!["tsy".to_string()]);
+        assert_eq!(value.admin, Some(admin));
+        assert_eq!(get_contract_version(&deps.storage).unwrap().version, CONTRACT_VERSION);
+    }
+
+    // Test migration errors
+    #[test]
+    fn test_migrate_errors() {
+        let mut deps = mock_dependencies();
+        set_contract_version(deps.as_mut().storage, "crates.io:other_contract", "0.1.0").unwrap();
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg { admin: None });
         match res {
-            _ => panic!("Must return Invalid Address error"),
+            Err(ContractError::WrongContract { .. }) => {}
+            _ => panic!("Must return Wrong Contract error"),
+        }
+
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "99.0.0").unwrap();
+        let res = migrate(deps.as_mut(), mock_env(), MigrateMsg { admin: None });
+        match res {
+            Err(ContractError::CannotMigrateDowngrade { .. }) => {}
+            _ => panic!("Must return Cannot Migrate Downgrade error"),
         }
     }
 
-    // Test transfer error 0
+    // Test migration of single-denom balances
     #[test]
-    fn test_transfer_error_0_transfer() {
+    fn test_migrate_from_v0_2() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        let depositor = deps.api.addr_make("depositor");
+        set_contract_version(deps.as_mut().storage, CONTRACT_NAME, "0.2.0").unwrap();
+        migrate::CONFIG_V0_2.save(deps.as_mut().storage, &migrate::ConfigV0_2 { allowed_denom: "tsy".to_string(), admin: Some(admin.clone()) }).unwrap();
+        migrate::BALANCES_V0_2.save(deps.as_mut().storage, depositor.clone(), &Uint128::new(500)).unwrap();
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let _res = migrate(deps.as_mut(), mock_env(), MigrateMsg { admin: None }).unwrap();
 
-        let msg = ExecuteMsg::Transfer {receiver: "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1".to_string(), amount: Uint128::new(0)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.allowed_denoms, vec!["tsy".to_string()]);
+        assert_eq!(value.admin, Some(admin));
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: depositor.to_string(), denom: "tsy".to_string() }).unwrap();
+        let value: GetDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposit, Uint128::new(500));
+        assert!(migrate::BALANCES_V0_2.is_empty(&deps.storage));
+        assert_eq!(TOTAL_DEPOSITS.load(&deps.storage, "tsy").unwrap(), Uint128::new(500));
+        assert!(crate::state::HOLDERS.has(&deps.storage, ("tsy", &depositor)));
+    }
+
+    // Test deposit, transfer and withdraw with several denoms
+    #[test]
+    fn test_multi_denom() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let receiver = deps.api.addr_make("receiver");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let funds = vec![Coin::new(100u128, "thi"), Coin::new(1000u128, "tsy"), Coin::new(5u128, "other")];
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetAccountDeposits { owner: depositor.to_string() }).unwrap();
+        let value: GetAccountDepositsResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposits, vec![Coin::new(100u128, "thi"), Coin::new(1000u128, "tsy")]);
+
+        let msg = ExecuteMsg::Transfer { receiver: receiver.to_string(), amount: Uint128::new(40), denom: "thi".to_string(), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(41), denom: "thi".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&receiver, &[]), msg);
         match res {
-            Err(ContractError::InvalidTransferAmount {}) => {}
-            _ => panic!("Must return Invalid Transfer Amount error"),
+            Err(ContractError::WithdrawFundsExceedsBalance {}) => {}
+            _ => panic!("Must return Withdraw Exceed Balance error"),
         }
+
+        let msg = QueryMsg::GetAllDeposit { denom: Some("thi".to_string()), start_after: None, limit: None, order: None };
+        let res_q = query(deps.as_ref(), mock_env(), msg).unwrap();
+        let value: GetAllDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposits.len(), 2);
+        assert_eq!(value.next_key, None);
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalDeposit { denom: "thi".to_string() }).unwrap();
+        let value: GetTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::new(100));
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalDeposit { denom: "tsy".to_string() }).unwrap();
+        let value: GetTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::new(1000));
     }
 
-    // Test transfer error sender no deposit
+    // Test coins of unknown denoms are refunded by default
     #[test]
-    fn test_transfer_error_sender_no_deposit() {
+    fn test_deposit_refunds_unexpected_funds() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let depositor = deps.api.addr_make("depositor");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let funds = vec![Coin::new(100u128, "tsy"), Coin::new(5u128, "other")];
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: depositor.to_string(), amount: vec![Coin::new(5u128, "other")] }.into());
+        assert!(res.attributes.iter().any(|a| a.key == "refunded" && a.value == "5other"));
+
+        let balance = BALANCES.load(&deps.storage, (&depositor, "tsy")).unwrap();
+        assert_eq!(balance, Uint128::new(100));
+    }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+    // Test coins of unknown denoms are rejected in strict mode
+    #[test]
+    fn test_deposit_strict_rejects_unexpected_funds() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
 
-        let msg = ExecuteMsg::Transfer {receiver:"cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1".to_string(), amount: Uint128::new(2),};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let msg = ExecuteMsg::UpdateConfig { unexpected_funds: Some(UnexpectedFundsPolicy::Strict) };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetState {}).unwrap();
+        let value: GetStateResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.unexpected_funds, UnexpectedFundsPolicy::Strict);
+
+        let funds = vec![Coin::new(100u128, "tsy"), Coin::new(5u128, "other")];
+        let res = execute(deps.as_mut(), mock_env(), message_info(&admin, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::UnexpectedFunds { denoms }) => assert_eq!(denoms, vec!["other".to_string()]),
+            _ => panic!("Must return Unexpected Funds error"),
+        }
+    }
+
+    // Test deposit assertions on the credited amount
+    #[test]
+    fn test_deposit_expected_amount() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: Some(Uint128::new(1000)), min_credit: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(999, "tsy")), msg.clone());
+        match res {
+            Err(ContractError::DepositAmountMismatch { expected, received, .. }) => {
+                assert_eq!(expected, Uint128::new(1000));
+                assert_eq!(received, Uint128::new(999));
+            }
+            _ => panic!("Must return Deposit Amount Mismatch error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(1000, "tsy")), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: Some(Uint128::new(50)) };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(49, "tsy")), msg.clone());
+        match res {
+            Err(ContractError::DepositBelowMinCredit { min_credit, credited, .. }) => {
+                assert_eq!(min_credit, Uint128::new(50));
+                assert_eq!(credited, Uint128::new(49));
+            }
+            _ => panic!("Must return Deposit Below Min Credit error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(50, "tsy")), msg).unwrap();
+
+        let balance = BALANCES.load(&deps.storage, (&depositor, "tsy")).unwrap();
+        assert_eq!(balance, Uint128::new(1050));
+    }
+
+    // Test deposit assertions refuse funds of several denoms
+    #[test]
+    fn test_deposit_expected_amount_multi_denom() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let funds = vec![Coin::new(1000u128, "thi"), Coin::new(1000u128, "tsy")];
+        let msg = ExecuteMsg::Deposit { expected: Some(Uint128::new(1000)), min_credit: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), msg);
+        match res {
+            Err(ContractError::SingleDenomRequired {}) => {}
+            _ => panic!("Must return Single Denom Required error"),
+        }
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: Some(Uint128::new(10)) };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), msg);
+        match res {
+            Err(ContractError::SingleDenomRequired {}) => {}
+            _ => panic!("Must return Single Denom Required error"),
+        }
+
+        // Without an assertion both denoms are credited
+        let msg = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), msg).unwrap();
+        assert_eq!(BALANCES.load(&deps.storage, (&depositor, "thi")).unwrap(), Uint128::new(1000));
+        assert_eq!(BALANCES.load(&deps.storage, (&depositor, "tsy")).unwrap(), Uint128::new(1000));
+    }
+
+    // Test deposit credited to another address
+    #[test]
+    fn test_deposit_for() {
+        let mut deps = mock_dependencies();
+        let payer = deps.api.addr_make("payer");
+        let employee = deps.api.addr_make("employee");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&payer, &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::DepositFor { recipient: employee.to_string() };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&payer, &coins(300, "tsy")), msg).unwrap();
+        assert_eq!(res.events.len(), 1);
+        assert_eq!(res.events[0].ty, "deposit_for");
+
+        assert_eq!(BALANCES.load(&deps.storage, (&employee, "tsy")).unwrap(), Uint128::new(300));
+        assert!(BALANCES.may_load(&deps.storage, (&payer, "tsy")).unwrap().is_none());
+    }
+
+    // Test deposit split between several addresses
+    #[test]
+    fn test_deposit_for_many() {
+        let mut deps = mock_dependencies();
+        let payer = deps.api.addr_make("payer");
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&payer, &[]), msg).unwrap();
+
+        let recipients = vec![(alice.to_string(), Uint128::new(100)), (bob.to_string(), Uint128::new(200))];
+        let msg = ExecuteMsg::DepositForMany { recipients: recipients.clone() };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&payer, &coins(299, "tsy")), msg.clone());
+        match res {
+            Err(ContractError::DepositSplitMismatch { total, received, .. }) => {
+                assert_eq!(total, Uint128::new(300));
+                assert_eq!(received, Uint128::new(299));
+            }
+            _ => panic!("Must return Deposit Split Mismatch error"),
+        }
+
+        let funds = vec![Coin::new(300u128, "tsy"), Coin::new(300u128, "thi")];
+        let res = execute(deps.as_mut(), mock_env(), message_info(&payer, &funds), msg.clone());
+        match res {
+            Err(ContractError::SingleDenomRequired {}) => {}
+            _ => panic!("Must return Single Denom Required error"),
+        }
+
+        let res = execute(deps.as_mut(), mock_env(), message_info(&payer, &coins(300, "tsy")), msg).unwrap();
+        assert_eq!(res.events.len(), 2);
+        assert_eq!(BALANCES.load(&deps.storage, (&alice, "tsy")).unwrap(), Uint128::new(100));
+        assert_eq!(BALANCES.load(&deps.storage, (&bob, "tsy")).unwrap(), Uint128::new(200));
+    }
+
+    // Test withdraw to another address
+    #[test]
+    fn test_withdraw_to_recipient() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let cold = deps.api.addr_make("cold");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(40), denom: "tsy".to_string(), recipient: Some(cold.to_string()), owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: cold.to_string(), amount: coins(40, "tsy") }.into());
+        assert_eq!(BALANCES.load(&deps.storage, (&depositor, "tsy")).unwrap(), Uint128::new(60));
+    }
+
+    // Test withdraw of the whole balance
+    #[test]
+    fn test_withdraw_all() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::WithdrawAll { recipient: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg.clone());
+        match res {
+            Err(ContractError::AddressHasNotDeposit {}) => {}
+            _ => panic!("Must return Address Has No Deposit error"),
+        }
+
+        let funds = vec![Coin::new(50u128, "thi"), Coin::new(100u128, "tsy")];
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg.clone()).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: depositor.to_string(), amount: funds }.into());
+
+        // No zero records are left behind
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetAccountDeposits { owner: depositor.to_string() }).unwrap();
+        let value: GetAccountDepositsResponse = from_json(&res_q).unwrap();
+        assert!(value.deposits.is_empty());
+
+        // A balance withdrawn to zero is no deposit left to withdraw
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(30, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let withdraw = ExecuteMsg::Withdraw { amount: Uint128::new(30), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), withdraw).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg);
         match res {
             Err(ContractError::AddressHasNotDeposit {}) => {}
             _ => panic!("Must return Address Has No Deposit error"),
         }
     }
 
-    // Test transfer error transfer exceed deposit
+    // Test the stored total follows deposits, transfers and withdraws
     #[test]
-    fn test_transfer_error_transfer_exceed_deposit() {
+    fn test_total_deposit() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+
+        let deposit = ExecuteMsg::Deposit { expected: None, min_credit: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &coins(300, "tsy")), deposit.clone()).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&bob, &coins(200, "tsy")), deposit.clone()).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&bob, &coins(50, "thi")), deposit).unwrap();
+        let msg = ExecuteMsg::Transfer { amount: Uint128::new(100), denom: "tsy".to_string(), receiver: bob.to_string(), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(120), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&bob, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), ExecuteMsg::WithdrawAll { recipient: None }).unwrap();
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalDeposit { denom: "tsy".to_string() }).unwrap();
+        let value: GetTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::new(180));
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalDeposit { denom: "thi".to_string() }).unwrap();
+        let value: GetTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::new(50));
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetTotalDeposit { denom: "other".to_string() }).unwrap();
+        let value: GetTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::zero());
+
+        let audit = QueryMsg::AuditTotalDeposit { denom: "tsy".to_string(), start_after: None, limit: None };
+        let res_q = query(deps.as_ref(), mock_env(), audit.clone()).unwrap();
+        let value: AuditTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.consistent, Some(true));
+        assert_eq!(value.computed, Uint128::new(180));
+        assert_eq!(value.next_key, None);
+
+        // A drifted total is reported by the audit
+        TOTAL_DEPOSITS.save(deps.as_mut().storage, "tsy", &Uint128::new(1), mock_env().block.height).unwrap();
+        let res_q = query(deps.as_ref(), mock_env(), audit).unwrap();
+        let value: AuditTotalDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.consistent, Some(false));
+        assert_eq!(value.stored, Uint128::new(1));
+        assert_eq!(value.computed, Uint128::new(180));
+
+        // Page by page the partial sums add up to the total
+        let carol = deps.api.addr_make("carol");
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&carol, &coins(70, "thi")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let mut computed = Uint128::zero();
+        let mut start_after = None;
+        let mut pages = 0;
+        loop {
+            let audit = QueryMsg::AuditTotalDeposit { denom: "tsy".to_string(), start_after, limit: Some(1) };
+            let value: AuditTotalDepositResponse = from_json(query(deps.as_ref(), mock_env(), audit).unwrap()).unwrap();
+            assert_eq!(value.consistent, None);
+            computed += value.computed;
+            pages += 1;
+            match value.next_key {
+                Some(next_key) => start_after = Some(next_key),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 2);
+        assert_eq!(computed, Uint128::new(180));
+    }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    // Test GetAllDeposit pages by owner in both orders
+    #[test]
+    fn test_all_deposits_pagination() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let mut owners: Vec<Addr> = (0..5).map(|i| deps.api.addr_make(&format!("owner{}", i))).collect();
+        owners.sort();
+        let funds = vec![Coin::new(10u128, "thi"), Coin::new(20u128, "tsy")];
+        for owner in &owners {
+            let _res = execute(deps.as_mut(), mock_env(), message_info(owner, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        }
 
-        let msg = ExecuteMsg::Transfer {receiver: "cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx1".to_string(), amount: Uint128::new(10000),};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        // Both deposits of an owner stay on the same page
+        let msg = QueryMsg::GetAllDeposit { denom: None, start_after: None, limit: Some(2), order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits.len(), 4);
+        assert_eq!(value.deposits[3].address, owners[1]);
+        assert_eq!(value.next_key, Some(owners[1].to_string()));
+
+        let msg = QueryMsg::GetAllDeposit { denom: Some("tsy".to_string()), start_after: value.next_key, limit: Some(2), order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let addresses: Vec<Addr> = value.deposits.iter().map(|d| d.address.clone()).collect();
+        assert_eq!(addresses, vec![owners[2].clone(), owners[3].clone()]);
+        assert!(value.deposits.iter().all(|d| d.denom == "tsy"));
+
+        let msg = QueryMsg::GetAllDeposit { denom: Some("tsy".to_string()), start_after: value.next_key, limit: Some(2), order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits.len(), 1);
+        assert_eq!(value.next_key, None);
+
+        let msg = QueryMsg::GetAllDeposit { denom: Some("thi".to_string()), start_after: Some(owners[2].to_string()), limit: None, order: Some(Order::Descending) };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let addresses: Vec<Addr> = value.deposits.iter().map(|d| d.address.clone()).collect();
+        assert_eq!(addresses, vec![owners[1].clone(), owners[0].clone()]);
+        assert_eq!(value.next_key, None);
+
+        // An owner who withdrew a denom is no longer listed under it
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(10), denom: "thi".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&owners[0], &[]), msg).unwrap();
+        let msg = QueryMsg::GetAllDeposit { denom: Some("thi".to_string()), start_after: None, limit: None, order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits.len(), 4);
+        assert!(value.deposits.iter().all(|d| d.address != owners[0]));
+
+        // A zero limit still returns one owner per page
+        let msg = QueryMsg::GetAllDeposit { denom: Some("thi".to_string()), start_after: None, limit: Some(0), order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits.len(), 1);
+        assert_eq!(value.next_key, Some(owners[1].to_string()));
+        let msg = QueryMsg::GetAllDeposit { denom: None, start_after: None, limit: Some(0), order: None };
+        let value: GetAllDepositResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits, vec![DepositEntry { address: owners[0].clone(), denom: "tsy".to_string(), totaldeposit: Uint128::new(20) }]);
+        assert_eq!(value.next_key, Some(owners[0].to_string()));
+    }
+
+    // Test GetDeposit for unknown and invalid owners, and the batch query
+    #[test]
+    fn test_get_deposits() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let stranger = deps.api.addr_make("stranger");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&depositor, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&depositor, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: stranger.to_string(), denom: "tsy".to_string() }).unwrap();
+        let value: GetDepositResponse = from_json(&res_q).unwrap();
+        assert_eq!(value.deposit, Uint128::zero());
+        assert!(!value.exists);
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: "not an address".to_string(), denom: "tsy".to_string() });
+        assert!(res.is_err());
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GetAccountDeposits { owner: "not an address".to_string() });
+        assert!(res.is_err());
+
+        let msg = QueryMsg::GetDeposits { owners: vec![depositor.to_string(), stranger.to_string()], denom: "tsy".to_string() };
+        let value: GetDepositsResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.deposits.len(), 2);
+        assert_eq!(value.deposits[0].address, depositor);
+        assert_eq!(value.deposits[0].deposit, Uint128::new(100));
+        assert!(value.deposits[0].exists);
+        assert_eq!(value.deposits[1].address, stranger);
+        assert!(!value.deposits[1].exists);
+    }
+
+    // Test balances and totals can be queried at past heights
+    #[test]
+    fn test_deposit_at_height() {
+        let mut deps = mock_dependencies();
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 100;
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&alice, &coins(300, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        env.block.height = 110;
+        let msg = ExecuteMsg::Transfer { amount: Uint128::new(100), denom: "tsy".to_string(), receiver: bob.to_string(), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), msg).unwrap();
+        env.block.height = 120;
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(50), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), env, message_info(&alice, &[]), msg).unwrap();
+
+        // A height sees the balances as they were before the changes made in that block
+        let cases = [(100, 0, 0, 0), (101, 300, 0, 300), (110, 300, 0, 300), (111, 200, 100, 300), (121, 150, 100, 250)];
+        for (height, alice_balance, bob_balance, total) in cases {
+            let msg = QueryMsg::GetDepositAtHeight { owner: alice.to_string(), denom: "tsy".to_string(), height };
+            let value: GetDepositAtHeightResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+            assert_eq!(value.deposit, Uint128::new(alice_balance), "alice at {}", height);
+            let msg = QueryMsg::GetDepositAtHeight { owner: bob.to_string(), denom: "tsy".to_string(), height };
+            let value: GetDepositAtHeightResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+            assert_eq!(value.deposit, Uint128::new(bob_balance), "bob at {}", height);
+            let msg = QueryMsg::GetTotalDepositAtHeight { denom: "tsy".to_string(), height };
+            let value: GetTotalDepositAtHeightResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+            assert_eq!(value.totaldeposit, Uint128::new(total), "total at {}", height);
+        }
+    }
+
+    // Test the ledger records every balance change of an account
+    #[test]
+    fn test_history() {
+        let mut deps = mock_dependencies();
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &coins(300, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let msg = ExecuteMsg::DepositFor { recipient: bob.to_string() };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &coins(20, "tsy")), msg).unwrap();
+        let msg = ExecuteMsg::Transfer { amount: Uint128::new(100), denom: "tsy".to_string(), receiver: bob.to_string(), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(50), denom: "tsy".to_string(), recipient: Some(bob.to_string()), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+
+        let msg = QueryMsg::GetHistory { address: alice.to_string(), start_after: None, limit: None };
+        let value: GetHistoryResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let entries: Vec<(u64, LedgerKind, Option<Addr>, Coin)> = value.entries
+            .into_iter()
+            .map(|e| (e.id, e.kind, e.counterparty, e.amount))
+            .collect();
+        assert_eq!(entries, vec![
+            (1, LedgerKind::Deposit, None, Coin::new(300u128, "tsy")),
+            (3, LedgerKind::TransferOut, Some(bob.clone()), Coin::new(100u128, "tsy")),
+            (5, LedgerKind::Withdraw, Some(bob.clone()), Coin::new(50u128, "tsy")),
+        ]);
+
+        let msg = QueryMsg::GetHistory { address: bob.to_string(), start_after: Some(2), limit: Some(1) };
+        let value: GetHistoryResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.entries.len(), 1);
+        assert_eq!(value.entries[0].kind, LedgerKind::TransferIn);
+        assert_eq!(value.entries[0].counterparty, Some(alice.clone()));
+        assert_eq!(value.entries[0].height, mock_env().block.height);
+    }
+
+    // Test memo and client reference on transfer and withdraw
+    #[test]
+    fn test_client_reference() {
+        let mut deps = mock_dependencies();
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &coins(300, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let msg = ExecuteMsg::Transfer {
+            amount: Uint128::new(100),
+            denom: "tsy".to_string(),
+            receiver: bob.to_string(),
+            owner: None,
+            memo: Some("invoice 42".to_string()),
+            client_ref: Some("ref-1".to_string()),
+        };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg.clone()).unwrap();
+        assert!(res.attributes.iter().any(|a| a.key == "memo" && a.value == "invoice 42"));
+        assert!(res.attributes.iter().any(|a| a.key == "client_ref" && a.value == "ref-1"));
+
+        // A retry with the same reference is rejected, even as a withdraw
+        let res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg);
         match res {
-            Err(ContractError::TransferFundsExceedsBalance {}) => {}
-            _ => panic!("Must return Transfer Exceed Balance error"),
+            Err(ContractError::DuplicateReference { client_ref }) => assert_eq!(client_ref, "ref-1"),
+            _ => panic!("Must return Duplicate Reference error"),
+        }
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(10), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: Some("ref-1".to_string()) };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg.clone());
+        match res {
+            Err(ContractError::DuplicateReference { .. }) => {}
+            _ => panic!("Must return Duplicate Reference error"),
         }
+        assert_eq!(BALANCES.load(&deps.storage, (&alice, "tsy")).unwrap(), Uint128::new(200));
+
+        // References are scoped to the sender
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&bob, &[]), msg).unwrap();
+
+        let msg = QueryMsg::GetClientRef { sender: alice.to_string(), client_ref: "ref-1".to_string() };
+        let value: GetClientRefResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let entry = value.entry.unwrap();
+        assert_eq!(entry.kind, LedgerKind::TransferOut);
+        assert_eq!(entry.amount, Coin::new(100u128, "tsy"));
+        assert_eq!(entry.memo, Some("invoice 42".to_string()));
+
+        let msg = QueryMsg::GetClientRef { sender: alice.to_string(), client_ref: "ref-2".to_string() };
+        let value: GetClientRefResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.entry, None);
+
+        // A spender has its own references, and its entry is read from the ledger of the owner
+        let carol = deps.api.addr_make("carol");
+        let msg = ExecuteMsg::IncreaseAllowance { spender: carol.to_string(), denom: "tsy".to_string(), amount: Uint128::new(100), expires: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg).unwrap();
+        let msg = ExecuteMsg::TransferFrom { owner: alice.to_string(), receiver: bob.to_string(), amount: Uint128::new(30), denom: "tsy".to_string(), client_ref: Some("ref-1".to_string()) };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&carol, &[]), msg).unwrap();
+        let msg = ExecuteMsg::WithdrawFrom { owner: alice.to_string(), amount: Uint128::new(30), denom: "tsy".to_string(), recipient: None, client_ref: Some("ref-1".to_string()) };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&carol, &[]), msg);
+        match res {
+            Err(ContractError::DuplicateReference { .. }) => {}
+            _ => panic!("Must return Duplicate Reference error"),
+        }
+        assert_eq!(BALANCES.load(&deps.storage, (&alice, "tsy")).unwrap(), Uint128::new(170));
+
+        let msg = QueryMsg::GetClientRef { sender: carol.to_string(), client_ref: "ref-1".to_string() };
+        let value: GetClientRefResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let entry = value.entry.unwrap();
+        assert_eq!(entry.kind, LedgerKind::TransferOut);
+        assert_eq!(entry.amount, Coin::new(30u128, "tsy"));
+        assert_eq!(entry.counterparty, Some(bob));
     }
 
-    // Test withdraw successfully
+    // Test allowances spent with TransferFrom and WithdrawFrom
     #[test]
-    fn test_withdraw_successfully() {
+    fn test_allowances() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let owner = deps.api.addr_make("owner");
+        let spender = deps.api.addr_make("spender");
+        let merchant = deps.api.addr_make("merchant");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&owner, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&owner, &coins(300, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let transfer_from = ExecuteMsg::TransferFrom { owner: owner.to_string(), receiver: merchant.to_string(), amount: Uint128::new(60), denom: "tsy".to_string(), client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&spender, &[]), transfer_from.clone());
+        match res {
+            Err(ContractError::InsufficientAllowance { .. }) => {}
+            _ => panic!("Must return Insufficient Allowance error"),
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        let msg = ExecuteMsg::IncreaseAllowance { spender: owner.to_string(), denom: "tsy".to_string(), amount: Uint128::new(100), expires: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&owner, &[]), msg);
+        match res {
+            Err(ContractError::CannotApproveSelf {}) => {}
+            _ => panic!("Must return Cannot Approve Self error"),
+        }
+        let msg = ExecuteMsg::IncreaseAllowance { spender: spender.to_string(), denom: "tsy".to_string(), amount: Uint128::new(100), expires: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&owner, &[]), msg).unwrap();
 
-        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(2)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
-        assert_eq!(res.messages.len(), 0);
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&spender, &[]), transfer_from.clone()).unwrap();
+        assert_eq!(BALANCES.load(&deps.storage, (&owner, "tsy")).unwrap(), Uint128::new(240));
+        assert_eq!(BALANCES.load(&deps.storage, (&merchant, "tsy")).unwrap(), Uint128::new(60));
+        let res = execute(deps.as_mut(), mock_env(), message_info(&spender, &[]), transfer_from);
+        match res {
+            Err(ContractError::InsufficientAllowance { allowance, .. }) => assert_eq!(allowance, Uint128::new(40)),
+            _ => panic!("Must return Insufficient Allowance error"),
+        }
 
-        let res_q = query(deps.as_ref(), mock_env(), QueryMsg::GetDeposit { owner: Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0") }).unwrap();
-        let value: GetDepositResponse = from_json(&res_q).unwrap();
-        assert_eq!(value.deposit, Uint128::new(998));
+        let msg = ExecuteMsg::WithdrawFrom { owner: owner.to_string(), amount: Uint128::new(40), denom: "tsy".to_string(), recipient: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&spender, &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: spender.to_string(), amount: coins(40, "tsy") }.into());
+        assert_eq!(BALANCES.load(&deps.storage, (&owner, "tsy")).unwrap(), Uint128::new(200));
+
+        // The allowance is removed once spent
+        let msg = QueryMsg::GetAllowance { owner: owner.to_string(), spender: spender.to_string(), denom: "tsy".to_string() };
+        let value: GetAllowanceResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.allowance, Uint128::zero());
+        assert!(ALLOWANCES_SPENDER.is_empty(&deps.storage));
+
+        // Expired allowances cannot be spent
+        let env = mock_env();
+        let expires = Expiration::AtHeight(env.block.height + 10);
+        let msg = ExecuteMsg::IncreaseAllowance { spender: spender.to_string(), denom: "tsy".to_string(), amount: Uint128::new(50), expires: Some(expires) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&owner, &[]), msg).unwrap();
+        let mut later = env.clone();
+        later.block.height += 10;
+        let msg = ExecuteMsg::TransferFrom { owner: owner.to_string(), receiver: merchant.to_string(), amount: Uint128::new(10), denom: "tsy".to_string(), client_ref: None };
+        let res = execute(deps.as_mut(), later, message_info(&spender, &[]), msg);
+        match res {
+            Err(ContractError::AllowanceExpired {}) => {}
+            _ => panic!("Must return Allowance Expired error"),
+        }
+
+        let msg = ExecuteMsg::IncreaseAllowance { spender: merchant.to_string(), denom: "tsy".to_string(), amount: Uint128::new(70), expires: None };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&owner, &[]), msg).unwrap();
+        let msg = ExecuteMsg::DecreaseAllowance { spender: merchant.to_string(), denom: "tsy".to_string(), amount: Uint128::new(20), expires: None };
+        let _res = execute(deps.as_mut(), env, message_info(&owner, &[]), msg).unwrap();
+
+        let msg = QueryMsg::AllAllowances { owner: owner.to_string(), start_after: None, limit: None };
+        let value: AllAllowancesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        let mut expected = vec![(spender.clone(), Uint128::new(50)), (merchant.clone(), Uint128::new(50))];
+        expected.sort();
+        let allowances: Vec<(Addr, Uint128)> = value.allowances.iter().map(|a| (a.spender.clone(), a.allowance)).collect();
+        assert_eq!(allowances, expected);
+
+        let msg = QueryMsg::AllAllowances { owner: owner.to_string(), start_after: Some((expected[0].0.to_string(), "tsy".to_string())), limit: None };
+        let value: AllAllowancesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.allowances.len(), 1);
+        assert_eq!(value.allowances[0].spender, expected[1].0);
+
+        let msg = QueryMsg::AllSpenderAllowances { spender: merchant.to_string(), start_after: None, limit: None };
+        let value: AllSpenderAllowancesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.allowances.len(), 1);
+        assert_eq!(value.allowances[0].owner, owner);
+        assert_eq!(value.allowances[0].expires, Expiration::Never {});
     }
 
-    // Test withdraw error fund not empty
+    // Test operators acting on the account that approved them
     #[test]
-    fn test_withdraw_error_fund_not_empty() {
+    fn test_operators() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let client = deps.api.addr_make("client");
+        let custody = deps.api.addr_make("custody");
+        let other = deps.api.addr_make("other");
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&client, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&client, &coins(300, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let transfer = ExecuteMsg::Transfer { amount: Uint128::new(100), denom: "tsy".to_string(), receiver: other.to_string(), owner: Some(client.to_string()), memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&custody, &[]), transfer.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let env = mock_env();
+        let msg = ExecuteMsg::ApproveOperator { operator: custody.to_string(), expires: Some(Expiration::AtHeight(env.block.height + 10)) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&client, &[]), msg).unwrap();
 
-        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(2)};
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&custody, &[]), transfer.clone()).unwrap();
+        assert_eq!(BALANCES.load(&deps.storage, (&client, "tsy")).unwrap(), Uint128::new(200));
+        assert_eq!(BALANCES.load(&deps.storage, (&other, "tsy")).unwrap(), Uint128::new(100));
+
+        // Withdrawn funds go to the owner unless a recipient is given
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(50), denom: "tsy".to_string(), recipient: None, owner: Some(client.to_string()), memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&custody, &[]), msg).unwrap();
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: client.to_string(), amount: coins(50, "tsy") }.into());
+        assert!(res.attributes.iter().any(|a| a.key == "operator" && a.value == custody.as_str()));
+
+        // The operator cannot act on accounts that did not approve it
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(50), denom: "tsy".to_string(), recipient: None, owner: Some(other.to_string()), memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&custody, &[]), msg);
         match res {
-            Err(ContractError::NoEmptyFunds {}) => {}
-            _ => panic!("Must return Fund Not Empty error"),
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+
+        let msg = QueryMsg::ListOperators { owner: client.to_string(), include_expired: None, start_after: None, limit: None };
+        let value: ListOperatorsResponse = from_json(query(deps.as_ref(), env.clone(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(value.operators.len(), 1);
+        assert_eq!(value.operators[0].operator, custody);
+
+        // Expired grants no longer work nor are listed by default
+        let mut later = env.clone();
+        later.block.height += 10;
+        let res = execute(deps.as_mut(), later.clone(), message_info(&custody, &[]), transfer.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let value: ListOperatorsResponse = from_json(query(deps.as_ref(), later.clone(), msg).unwrap()).unwrap();
+        assert!(value.operators.is_empty());
+        let msg = QueryMsg::ListOperators { owner: client.to_string(), include_expired: Some(true), start_after: None, limit: None };
+        let value: ListOperatorsResponse = from_json(query(deps.as_ref(), later, msg).unwrap()).unwrap();
+        assert_eq!(value.operators.len(), 1);
+
+        let msg = ExecuteMsg::ApproveOperator { operator: custody.to_string(), expires: None };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&client, &[]), msg).unwrap();
+        let msg = ExecuteMsg::RevokeOperator { operator: custody.to_string() };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&client, &[]), msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), message_info(&custody, &[]), transfer);
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let res = execute(deps.as_mut(), env, message_info(&client, &[]), msg);
+        match res {
+            Err(ContractError::OperatorNotFound { .. }) => {}
+            _ => panic!("Must return Operator Not Found error"),
         }
     }
 
-    // Test withdraw error 0
+    // Test the CW20 queries on the denom exposed as a token
     #[test]
-    fn test_withdraw_error_0_withdraw() {
+    fn test_cw20_queries() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        let alice = deps.api.addr_make("alice");
+        let msg = InstantiateMsg { allowed_denoms: vec!["utsy".to_string(), "thi".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+        let funds = vec![Coin::new(20u128, "thi"), Coin::new(300u128, "utsy")];
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &funds), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &coins(20, "thi")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {}).is_err());
+
+        deps.querier.bank.set_denom_metadata(&[DenomMetadata {
+            description: String::new(),
+            denom_units: vec![
+                DenomUnit { denom: "utsy".to_string(), exponent: 0, aliases: vec![] },
+                DenomUnit { denom: "tsy".to_string(), exponent: 6, aliases: vec![] },
+            ],
+            base: "utsy".to_string(),
+            display: "tsy".to_string(),
+            name: "Treasury".to_string(),
+            symbol: "TSY".to_string(),
+            uri: String::new(),
+            uri_hash: String::new(),
+        }]);
+        let msg = ExecuteMsg::SetTokenInfo { denom: "utsy".to_string(), name: Some("Vault Treasury".to_string()), symbol: None, decimals: None };
+        let res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&admin, &[]), msg).unwrap();
+
+        let value: cw20::TokenInfoResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::TokenInfo {}).unwrap()).unwrap();
+        assert_eq!(value.name, "Vault Treasury");
+        assert_eq!(value.symbol, "TSY");
+        assert_eq!(value.decimals, 6);
+        assert_eq!(value.total_supply, Uint128::new(300));
+
+        let value: cw20::BalanceResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Balance { address: alice.to_string() }).unwrap()).unwrap();
+        assert_eq!(value.balance, Uint128::new(300));
+        let value: cw20::BalanceResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Balance { address: admin.to_string() }).unwrap()).unwrap();
+        assert_eq!(value.balance, Uint128::zero());
+
+        // Only holders of the exposed denom are listed
+        let msg = QueryMsg::AllAccounts { start_after: None, limit: None };
+        let value: cw20::AllAccountsResponse = from_json(query(deps.as_ref(), mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(value.accounts, vec![alice.to_string()]);
+
+        // An emptied balance drops out of the list
+        let transfer = ExecuteMsg::Transfer { amount: Uint128::new(300), denom: "utsy".to_string(), receiver: admin.to_string(), owner: None, memo: None, client_ref: None };
+        let _res = execute(deps.as_mut(), mock_env(), message_info(&alice, &[]), transfer).unwrap();
+        let value: cw20::AllAccountsResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(value.accounts, vec![admin.to_string()]);
+    }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+    // Test a deficit is reported when the contract holds less than the deposits
+    #[test]
+    fn test_solvency_deficit() {
+        let mut deps = mock_dependencies();
+        let depositor = deps.api.addr_make("depositor");
+        let env = mock_env();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), env.clone(), message_info(&depositor, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        deps.querier.bank.update_balance(&env.contract.address, coins(80, "tsy"));
+
+        let value: GetSolvencyResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetSolvency {}).unwrap()).unwrap();
+        assert!(!value.solvent);
+        assert_eq!(value.denoms[0].deficit, Uint128::new(20));
+        assert_eq!(value.denoms[0].surplus, Uint128::zero());
+
+        let res = execute(deps.as_mut(), env, message_info(&depositor, &[]), ExecuteMsg::SweepSurplus { recipient: depositor.to_string() });
+        match res {
+            Err(ContractError::NoSurplus {}) => {}
+            _ => panic!("Must return No Surplus error"),
+        }
+    }
 
-        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(0)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+    // Test pausing single operations, the guardian role and the auto-unpause
+    #[test]
+    fn test_pause() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let guardian = deps.api.addr_make("guardian");
+        let depositor = deps.api.addr_make("depositor");
+        let env = mock_env();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let pause = ExecuteMsg::Pause { operations: Some(vec![Operation::Withdraw]), until: Some(Expiration::AtHeight(env.block.height + 10)) };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), pause.clone());
         match res {
-            Err(ContractError::InvalidWithdrawAmount {}) => {}
-            _ => panic!("Must return Invalid Withdraw Amount error"),
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let msg = ExecuteMsg::SetGuardian { guardian: Some(guardian.to_string()) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), pause).unwrap();
+
+        let withdraw = ExecuteMsg::Withdraw { amount: Uint128::new(10), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &[]), withdraw.clone());
+        match res {
+            Err(ContractError::Paused { operation }) => assert_eq!(operation, "withdraw"),
+            _ => panic!("Must return Paused error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &[]), ExecuteMsg::WithdrawAll { recipient: None });
+        match res {
+            Err(ContractError::Paused { .. }) => {}
+            _ => panic!("Must return Paused error"),
         }
+        // Other operations keep working
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let value: GetPauseStatusResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetPauseStatus {}).unwrap()).unwrap();
+        let paused: Vec<Operation> = value.operations.iter().filter(|o| o.paused).map(|o| o.operation).collect();
+        assert_eq!(paused, vec![Operation::Withdraw]);
+
+        // The pause ends by itself at its expiry
+        let mut later = env.clone();
+        later.block.height += 10;
+        let _res = execute(deps.as_mut(), later.clone(), message_info(&depositor, &[]), withdraw.clone()).unwrap();
+        let value: GetPauseStatusResponse = from_json(query(deps.as_ref(), later, QueryMsg::GetPauseStatus {}).unwrap()).unwrap();
+        assert!(value.operations.iter().all(|o| !o.paused));
+
+        // Pausing everything, only the admin can unpause
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), ExecuteMsg::Pause { operations: None, until: None }).unwrap();
+        let res = execute(deps.as_mut(), env.clone(), message_info(&depositor, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::Paused { operation }) => assert_eq!(operation, "deposit"),
+            _ => panic!("Must return Paused error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), ExecuteMsg::Unpause { operations: None });
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::Unpause { operations: None }).unwrap();
+        let _res = execute(deps.as_mut(), env, message_info(&depositor, &[]), withdraw).unwrap();
     }
 
-    // Test withdraw error receiver no deposit
+    // Test the guardian cannot shorten a pause set by the admin
     #[test]
-    fn test_withdraw_error_sender_no_deposit() {
+    fn test_guardian_cannot_shorten_pause() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        let guardian = deps.api.addr_make("guardian");
+        let depositor = deps.api.addr_make("depositor");
+        let env = mock_env();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let msg = ExecuteMsg::SetGuardian { guardian: Some(guardian.to_string()) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::Pause { operations: None, until: None }).unwrap();
+
+        let msg = ExecuteMsg::Pause { operations: Some(vec![Operation::Deposit]), until: Some(Expiration::AtHeight(env.block.height + 1)) };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), msg).unwrap();
+        assert!(res.attributes.iter().any(|a| a.key == "until" && a.value == Expiration::Never {}.to_string()));
+        let mut later = env.clone();
+        later.block.height += 1;
+        let res = execute(deps.as_mut(), later.clone(), message_info(&depositor, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::Paused { operation }) => assert_eq!(operation, "deposit"),
+            _ => panic!("Must return Paused error"),
+        }
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info, msg).unwrap();
+        // The guardian can still extend a pause with an expiry
+        let msg = ExecuteMsg::Pause { operations: Some(vec![Operation::Withdraw]), until: Some(Expiration::AtHeight(env.block.height + 5)) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let msg = ExecuteMsg::Pause { operations: Some(vec![Operation::Withdraw]), until: Some(Expiration::AtHeight(env.block.height + 20)) };
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&guardian, &[]), msg).unwrap();
+        let value: GetPauseStatusResponse = from_json(query(deps.as_ref(), later, QueryMsg::GetPauseStatus {}).unwrap()).unwrap();
+        let withdraw = value.operations.iter().find(|o| o.operation == Operation::Withdraw).unwrap();
+        assert_eq!(withdraw.until, Some(Expiration::AtHeight(env.block.height + 20)));
+        let deposit = value.operations.iter().find(|o| o.operation == Operation::Deposit).unwrap();
+        assert_eq!(deposit.until, Some(Expiration::Never {}));
+    }
 
-        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(2)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx2"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+    // Test shutdown closes the contract and emergency withdraws share a shortfall pro rata
+    #[test]
+    fn test_shutdown() {
+        let mut deps = mock_dependencies();
+        let admin = deps.api.addr_make("admin");
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let env = mock_env();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&alice, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&bob, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        deps.querier.bank.update_balance(&env.contract.address, coins(150, "tsy"));
+
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), ExecuteMsg::EmergencyWithdraw {});
+        match res {
+            Err(ContractError::NotShutDown {}) => {}
+            _ => panic!("Must return Not Shut Down error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), ExecuteMsg::Shutdown {});
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::Shutdown {}).unwrap();
+        let value: GetStateResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetState {}).unwrap()).unwrap();
+        assert!(value.shutdown);
+
+        // Deposits, transfers and withdraws are closed, and so is a second shutdown
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
+        match res {
+            Err(ContractError::ShutDown {}) => {}
+            _ => panic!("Must return Shut Down error"),
+        }
+        let msg = ExecuteMsg::Transfer { amount: Uint128::new(10), denom: "tsy".to_string(), receiver: bob.to_string(), owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), msg);
+        match res {
+            Err(ContractError::ShutDown {}) => {}
+            _ => panic!("Must return Shut Down error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), ExecuteMsg::WithdrawAll { recipient: None });
+        match res {
+            Err(ContractError::ShutDown {}) => {}
+            _ => panic!("Must return Shut Down error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::Shutdown {});
+        match res {
+            Err(ContractError::ShutDown {}) => {}
+            _ => panic!("Must return Shut Down error"),
+        }
+
+        // Both depositors get 75% of their balance, whoever exits first
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), ExecuteMsg::EmergencyWithdraw {}).unwrap();
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: alice.to_string(), amount: coins(75, "tsy") }.into());
+        deps.querier.bank.update_balance(&env.contract.address, coins(75, "tsy"));
+        let res = execute(deps.as_mut(), env.clone(), message_info(&bob, &[]), ExecuteMsg::EmergencyWithdraw {}).unwrap();
+        assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: bob.to_string(), amount: coins(75, "tsy") }.into());
+
+        let value: GetTotalDepositResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::GetTotalDeposit { denom: "tsy".to_string() }).unwrap()).unwrap();
+        assert_eq!(value.totaldeposit, Uint128::zero());
+        let res = execute(deps.as_mut(), env, message_info(&alice, &[]), ExecuteMsg::EmergencyWithdraw {});
         match res {
             Err(ContractError::AddressHasNotDeposit {}) => {}
-            _ => panic!("Must return Address Has No Deposit error"),
+            _ => panic!("Must return Address Has Not Deposit error"),
         }
     }
 
-    // Test withdraw error withdraw exceed deposit
+    // Test a frozen account can neither move its deposits nor receive any
     #[test]
-    fn test_withdraw_error_withdraw_exceed_deposit() {
+    fn test_freeze() {
         let mut deps = mock_dependencies();
-        let msg = InstantiateMsg { allowed_denom: "tsy".to_string() };
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &coins(1000, "tsy".to_string()));
-        let _res = instantiate(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let admin = deps.api.addr_make("admin");
+        let alice = deps.api.addr_make("alice");
+        let bob = deps.api.addr_make("bob");
+        let env = mock_env();
+        let msg = InstantiateMsg { allowed_denoms: vec!["tsy".to_string()], admin: None, unexpected_funds: None };
+        let _res = instantiate(deps.as_mut(), env.clone(), message_info(&admin, &[]), msg).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&alice, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&bob, &coins(100, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None }).unwrap();
+
+        let freeze = ExecuteMsg::Freeze { address: bob.to_string(), reason: "sanctions list".to_string() };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), freeze.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => {}
+            _ => panic!("Must return Unauthorized error"),
+        }
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), freeze).unwrap();
 
-        let msg = ExecuteMsg::Deposit{};
-        let _res = execute(deps.as_mut(), mock_env(), info.clone(), msg).unwrap();
+        let value: ListFrozenResponse = from_json(query(deps.as_ref(), env.clone(), QueryMsg::ListFrozen { start_after: None, limit: None }).unwrap()).unwrap();
+        assert_eq!(value.accounts.len(), 1);
+        assert_eq!(value.accounts[0].address, bob);
+        assert_eq!(value.accounts[0].reason, "sanctions list");
+        assert_eq!(value.accounts[0].since, env.block.time);
 
-        let msg = ExecuteMsg::Withdraw {amount: Uint128::new(10000)};
-        let info = message_info(&Addr::unchecked("cosmos1xv9tklw7d82sezh9ha4c6w7422k3halglxxxx0"), &[]);
-        let res = execute(deps.as_mut(), mock_env(), info, msg);
+        let res = execute(deps.as_mut(), env.clone(), message_info(&bob, &coins(10, "tsy")), ExecuteMsg::Deposit { expected: None, min_credit: None });
         match res {
-            Err(ContractError::WithdrawFundsExceedsBalance {}) => {}
-            _ => panic!("Must return Withdraw Exceed Balance error"),
+            Err(ContractError::AccountFrozen { address }) => assert_eq!(address, bob.to_string()),
+            _ => panic!("Must return Account Frozen error"),
+        }
+        let msg = ExecuteMsg::Withdraw { amount: Uint128::new(10), denom: "tsy".to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&bob, &[]), msg);
+        match res {
+            Err(ContractError::AccountFrozen { .. }) => {}
+            _ => panic!("Must return Account Frozen error"),
+        }
+        // Nor can the account receive a transfer or a deposit made for it
+        let transfer = ExecuteMsg::Transfer { amount: Uint128::new(10), denom: "tsy".to_string(), receiver: bob.to_string(), owner: None, memo: None, client_ref: None };
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), transfer.clone());
+        match res {
+            Err(ContractError::AccountFrozen { address }) => assert_eq!(address, bob.to_string()),
+            _ => panic!("Must return Account Frozen error"),
+        }
+        let res = execute(deps.as_mut(), env.clone(), message_info(&alice, &coins(10, "tsy")), ExecuteMsg::DepositFor { recipient: bob.to_string() });
+        match res {
+            Err(ContractError::AccountFrozen { .. }) => {}
+            _ => panic!("Must return Account Frozen error"),
+        }
+
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&admin, &[]), ExecuteMsg::Unfreeze { address: bob.to_string() }).unwrap();
+        let _res = execute(deps.as_mut(), env.clone(), message_info(&alice, &[]), transfer).unwrap();
+        let res = execute(deps.as_mut(), env, message_info(&admin, &[]), ExecuteMsg::Unfreeze { address: bob.to_string() });
+        match res {
+            Err(ContractError::AccountNotFrozen { .. }) => {}
+            _ => panic!("Must return Account Not Frozen error"),
         }
     }
 }
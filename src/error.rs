@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,27 +6,129 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("Semver parsing error: {msg}")]
+    SemVer { msg: String },
+
     #[error("Unauthorized")]
     Unauthorized {},
 
     #[error("Amount to deposit must be greater than 0")]
-    InvalidDepositAmount(),
+    InvalidDepositAmount {},
 
     #[error("Funds field should be empty")]
-    NoEmptyFunds(),
+    NoEmptyFunds {},
 
     #[error("Amount to transfer must be greater than 0")]
-    InvalidTransferAmount(),
+    InvalidTransferAmount {},
 
     #[error("Amount to withdraw must be greater than 0")]
-    InvalidWithdrawAmount(),
+    InvalidWithdrawAmount {},
 
     #[error("Balance is lower than amount to transfer")]
-    TransferFundsExceedsBalance(),
+    TransferFundsExceedsBalance {},
 
     #[error("The address selected does not have a deposit")]
-    AddressHasNotDeposit(),
+    AddressHasNotDeposit {},
 
     #[error("Balance is lower than amount to withdraw")]
-    WithdrawFundsExceedsBalance(),
+    WithdrawFundsExceedsBalance {},
+
+    #[error("A denom cannot be removed while deposits of it are held")]
+    DepositsOutstanding {},
+
+    #[error("There is no pending ownership transfer")]
+    NoPendingOwner {},
+
+    #[error("The ownership transfer offer has expired")]
+    OwnershipProposalExpired {},
+
+    #[error("The expiry of the ownership transfer offer is already reached")]
+    InvalidExpiry {},
+
+    #[error("Cannot migrate from a different contract: expected {expected}, found {found}")]
+    WrongContract { expected: String, found: String },
+
+    #[error("Cannot migrate from version {stored} to older version {new}")]
+    CannotMigrateDowngrade { stored: String, new: String },
+
+    #[error("Denom or token {denom} is not registered")]
+    DenomNotFound { denom: String },
+
+    #[error("Denom {denom} is already registered")]
+    DenomAlreadyRegistered { denom: String },
+
+    #[error("Deposits of denom {denom} are disabled")]
+    DenomDisabled { denom: String },
+
+    #[error("Deposit of {denom} is below the minimum of {min}")]
+    DepositBelowMinimum { denom: String, min: Uint128 },
+
+    #[error("Balance of {denom} would exceed the per-account cap of {cap}")]
+    AccountCapExceeded { denom: String, cap: Uint128 },
+
+    #[error("Expected to deposit {expected}{denom} but received {received}{denom}")]
+    DepositAmountMismatch { denom: String, expected: Uint128, received: Uint128 },
+
+    #[error("Deposit credits {credited}{denom}, below the minimum credit of {min_credit}{denom}")]
+    DepositBelowMinCredit { denom: String, min_credit: Uint128, credited: Uint128 },
+
+    #[error("Recipient amounts sum to {total}{denom} but {received}{denom} was sent")]
+    DepositSplitMismatch { denom: String, total: Uint128, received: Uint128 },
+
+    #[error("Funds of a single accepted denom must be sent")]
+    SingleDenomRequired {},
+
+    #[error("Unexpected funds sent with the deposit: {}", .denoms.join(", "))]
+    UnexpectedFunds { denoms: Vec<String> },
+
+    #[error("Client reference {client_ref} was already used by the sender")]
+    DuplicateReference { client_ref: String },
+
+    #[error("The contract is shut down")]
+    ShutDown {},
+
+    #[error("Emergency withdraws are only open once the contract is shut down")]
+    NotShutDown {},
+
+    #[error("{operation} is paused")]
+    Paused { operation: String },
+
+    #[error("Account {address} is frozen")]
+    AccountFrozen { address: String },
+
+    #[error("Account {address} is not frozen")]
+    AccountNotFrozen { address: String },
+
+    #[error("Account {address} is not a member")]
+    NotAMember { address: String },
+
+    #[error("The expiry of the pause is already reached")]
+    InvalidPauseExpiry {},
+
+    #[error("The contract holds no more than the deposits")]
+    NoSurplus {},
+
+    #[error("Cannot approve own account")]
+    CannotApproveSelf {},
+
+    #[error("The expiry of the allowance is already reached")]
+    InvalidAllowanceExpiry {},
+
+    #[error("The expiry of the operator grant is already reached")]
+    InvalidOperatorExpiry {},
+
+    #[error("{operator} is not an operator of the sender")]
+    OperatorNotFound { operator: String },
+
+    #[error("Allowance is expired")]
+    AllowanceExpired {},
+
+    #[error("Allowance of {allowance}{denom} is too low")]
+    InsufficientAllowance { denom: String, allowance: Uint128 },
+}
+
+impl From<semver::Error> for ContractError {
+    fn from(err: semver::Error) -> Self {
+        Self::SemVer { msg: err.to_string() }
+    }
 }
@@ -1,71 +1,336 @@
-// #[cfg(test)]
-// mod tests {
-//     use crate::helpers::CwTemplateContract;
-//     use crate::msg::InstantiateMsg;
-//     use cosmwasm_std::{Addr, Coin, Empty, Uint128};
-//     use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
-
-//     pub fn contract_template() -> Box<dyn Contract<Empty>> {
-//         let contract = ContractWrapper::new(
-//             crate::contract::execute,
-//             crate::contract::instantiate,
-//             crate::contract::query,
-//         );
-//         Box::new(contract)
-//     }
-
-//     const USER: &str = "USER";
-//     const ADMIN: &str = "ADMIN";
-//     const NATIVE_DENOM: &str = "denom";
-
-//     fn mock_app() -> App {
-//         AppBuilder::new().build(|router, _, storage| {
-//             router
-//                 .bank
-//                 .init_balance(
-//                     storage,
-//                     &Addr::unchecked(USER),
-//                     vec![Coin {
-//                         denom: NATIVE_DENOM.to_string(),
-//                         amount: Uint128::new(1),
-//                     }],
-//                 )
-//                 .unwrap();
-//         })
-//     }
-
-//     fn proper_instantiate() -> (App, CwTemplateContract) {
-//         let mut app = mock_app();
-//         let cw_template_id = app.store_code(contract_template());
-
-//         let msg = InstantiateMsg { count: 1i32 };
-//         let cw_template_contract_addr = app
-//             .instantiate_contract(
-//                 cw_template_id,
-//                 Addr::unchecked(ADMIN),
-//                 &msg,
-//                 &[],
-//                 "test",
-//                 None,
-//             )
-//             .unwrap();
-
-//         let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
-
-//         (app, cw_template_contract)
-//     }
-
-//     mod count {
-//         use super::*;
-//         use crate::msg::ExecuteMsg;
-
-//         #[test]
-//         fn count() {
-//             let (mut app, cw_template_contract) = proper_instantiate();
-
-//             let msg = ExecuteMsg::Increment {};
-//             let cosmos_msg = cw_template_contract.call(msg).unwrap();
-//             app.execute(Addr::unchecked(USER), cosmos_msg).unwrap();
-//         }
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use crate::msg::{AssetInfo, DenomParams, ExecuteMsg, GetDepositResponse, GetSolvencyResponse, GetStateResponse, InstantiateMsg, Operation, QueryMsg, ReceiveMsg, SudoMsg};
+    use crate::ContractError;
+    use cosmwasm_std::{coins, to_json_binary, Addr, Empty, Uint128};
+    use cw20::{AllAccountsResponse, BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg, TokenInfoResponse};
+    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
+    use cw_utils::Expiration;
+
+    pub fn contract_template() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_sudo(crate::contract::sudo);
+        Box::new(contract)
+    }
+
+    pub fn contract_cw20() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            cw20_base::contract::execute,
+            cw20_base::contract::instantiate,
+            cw20_base::contract::query,
+        );
+        Box::new(contract)
+    }
+
+    // Mock of a cw4 group: members are set at instantiation and only the Member query is answered
+    mod registry {
+        use cosmwasm_schema::cw_serde;
+        use cosmwasm_std::{to_json_binary, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError, StdResult};
+        use cw4::{Cw4QueryMsg, MemberResponse};
+        use cw_storage_plus::Map;
+
+        const MEMBERS: Map<&str, u64> = Map::new("members");
+
+        #[cw_serde]
+        pub struct InstantiateMsg {
+            pub members: Vec<String>,
+        }
+
+        pub fn instantiate(deps: DepsMut, _env: Env, _info: MessageInfo, msg: InstantiateMsg) -> StdResult<Response> {
+            for member in msg.members {
+                MEMBERS.save(deps.storage, &member, &1)?;
+            }
+            Ok(Response::new())
+        }
+
+        pub fn execute(_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty) -> StdResult<Response> {
+            Err(StdError::generic_err("not supported"))
+        }
+
+        pub fn query(deps: Deps, _env: Env, msg: Cw4QueryMsg) -> StdResult<Binary> {
+            match msg {
+                Cw4QueryMsg::Member { addr, .. } => to_json_binary(&MemberResponse { weight: MEMBERS.may_load(deps.storage, &addr)? }),
+                _ => Err(StdError::generic_err("not supported")),
+            }
+        }
+    }
+
+    pub fn contract_registry() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(registry::execute, registry::instantiate, registry::query);
+        Box::new(contract)
+    }
+
+    const USER: &str = "USER";
+    const ADMIN: &str = "ADMIN";
+    const NATIVE_DENOM: &str = "denom";
+
+    fn mock_app() -> App {
+        AppBuilder::new().build(|router, api, storage| {
+            router
+                .bank
+                .init_balance(storage, &api.addr_make(USER), coins(1000, NATIVE_DENOM))
+                .unwrap();
+        })
+    }
+
+    fn instantiate_cw20(app: &mut App, symbol: &str) -> Addr {
+        let cw20_id = app.store_code(contract_cw20());
+        let msg = cw20_base::msg::InstantiateMsg {
+            name: format!("{} token", symbol),
+            symbol: symbol.to_string(),
+            decimals: 6,
+            initial_balances: vec![Cw20Coin {
+                address: app.api().addr_make(USER).to_string(),
+                amount: Uint128::new(1000),
+            }],
+            mint: None,
+            marketing: None,
+        };
+        app.instantiate_contract(cw20_id, app.api().addr_make(ADMIN), &msg, &[], "cw20", None)
+            .unwrap()
+    }
+
+    // Returns the app, the vault and a CW20 token registered in the vault
+    fn proper_instantiate() -> (App, Addr, Addr) {
+        let mut app = mock_app();
+        let admin = app.api().addr_make(ADMIN);
+        let cw_template_id = app.store_code(contract_template());
+
+        let msg = InstantiateMsg { allowed_denoms: vec![NATIVE_DENOM.to_string()], admin: None, unexpected_funds: None };
+        let vault = app
+            .instantiate_contract(cw_template_id, admin.clone(), &msg, &[], "vault", None)
+            .unwrap();
+
+        let token = instantiate_cw20(&mut app, "TKN");
+        let msg = ExecuteMsg::AddDenom { asset: AssetInfo::Cw20(token.clone()), params: DenomParams::default() };
+        app.execute_contract(admin, vault.clone(), &msg, &[]).unwrap();
+
+        (app, vault, token)
+    }
+
+    fn cw20_balance(app: &App, token: &Addr, address: &Addr) -> Uint128 {
+        let res: BalanceResponse = app
+            .wrap()
+            .query_wasm_smart(token, &Cw20QueryMsg::Balance { address: address.to_string() })
+            .unwrap();
+        res.balance
+    }
+
+    fn vault_deposit(app: &App, vault: &Addr, owner: &Addr, denom: &str) -> Uint128 {
+        let res: GetDepositResponse = app
+            .wrap()
+            .query_wasm_smart(vault, &QueryMsg::GetDeposit { owner: owner.to_string(), denom: denom.to_string() })
+            .unwrap();
+        res.deposit
+    }
+
+    mod native {
+        use super::*;
+
+        #[test]
+        fn sweep_surplus() {
+            let (mut app, vault, token) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+            let admin = app.api().addr_make(ADMIN);
+
+            app.execute_contract(user.clone(), vault.clone(), &ExecuteMsg::Deposit { expected: None, min_credit: None }, &coins(400, NATIVE_DENOM))
+                .unwrap();
+            // Coins and tokens sent to the vault outside of a deposit
+            app.send_tokens(user.clone(), vault.clone(), &coins(50, NATIVE_DENOM)).unwrap();
+            let msg = Cw20ExecuteMsg::Transfer { recipient: vault.to_string(), amount: Uint128::new(30) };
+            app.execute_contract(user.clone(), token.clone(), &msg, &[]).unwrap();
+
+            let res: GetSolvencyResponse = app.wrap().query_wasm_smart(&vault, &QueryMsg::GetSolvency {}).unwrap();
+            assert!(res.solvent);
+            let native = res.denoms.iter().find(|d| d.denom == NATIVE_DENOM).unwrap();
+            assert_eq!((native.held, native.deposits, native.surplus), (Uint128::new(450), Uint128::new(400), Uint128::new(50)));
+
+            let msg = ExecuteMsg::SweepSurplus { recipient: admin.to_string() };
+            let err = app.execute_contract(user.clone(), vault.clone(), &msg, &[]).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::Unauthorized {}.to_string());
+            app.execute_contract(admin.clone(), vault.clone(), &msg, &[]).unwrap();
+            assert_eq!(app.wrap().query_balance(&admin, NATIVE_DENOM).unwrap().amount, Uint128::new(50));
+            assert_eq!(cw20_balance(&app, &token, &admin), Uint128::new(30));
+
+            // Deposits are untouched and nothing is left to sweep
+            assert_eq!(app.wrap().query_balance(&vault, NATIVE_DENOM).unwrap().amount, Uint128::new(400));
+            let err = app.execute_contract(admin, vault.clone(), &msg, &[]).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::NoSurplus {}.to_string());
+        }
+
+        #[test]
+        fn deposit_and_withdraw() {
+            let (mut app, vault, _) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+
+            app.execute_contract(user.clone(), vault.clone(), &ExecuteMsg::Deposit { expected: None, min_credit: None }, &coins(400, NATIVE_DENOM))
+                .unwrap();
+            assert_eq!(vault_deposit(&app, &vault, &user, NATIVE_DENOM), Uint128::new(400));
+
+            let msg = ExecuteMsg::Withdraw { amount: Uint128::new(150), denom: NATIVE_DENOM.to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+            app.execute_contract(user.clone(), vault.clone(), &msg, &[]).unwrap();
+            assert_eq!(app.wrap().query_balance(&user, NATIVE_DENOM).unwrap().amount, Uint128::new(750));
+            assert_eq!(vault_deposit(&app, &vault, &user, NATIVE_DENOM), Uint128::new(250));
+        }
+    }
+
+    mod membership {
+        use super::*;
+
+        #[test]
+        fn only_members_are_credited() {
+            let (mut app, vault, _) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+            let admin = app.api().addr_make(ADMIN);
+            let outsider = app.api().addr_make("outsider");
+
+            let registry_id = app.store_code(contract_registry());
+            let msg = registry::InstantiateMsg { members: vec![user.to_string()] };
+            let registry = app.instantiate_contract(registry_id, admin.clone(), &msg, &[], "registry", None).unwrap();
+            let msg = ExecuteMsg::SetMembership { membership: Some(registry.to_string()) };
+            app.execute_contract(admin.clone(), vault.clone(), &msg, &[]).unwrap();
+
+            app.execute_contract(user.clone(), vault.clone(), &ExecuteMsg::Deposit { expected: None, min_credit: None }, &coins(400, NATIVE_DENOM))
+                .unwrap();
+            let err = app
+                .execute_contract(user.clone(), vault.clone(), &ExecuteMsg::DepositFor { recipient: outsider.to_string() }, &coins(100, NATIVE_DENOM))
+                .unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::NotAMember { address: outsider.to_string() }.to_string());
+            let transfer = ExecuteMsg::Transfer { amount: Uint128::new(100), denom: NATIVE_DENOM.to_string(), receiver: outsider.to_string(), owner: None, memo: None, client_ref: None };
+            let err = app.execute_contract(user.clone(), vault.clone(), &transfer, &[]).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::NotAMember { address: outsider.to_string() }.to_string());
+
+            // Without a registry anyone is served again
+            app.execute_contract(admin, vault.clone(), &ExecuteMsg::SetMembership { membership: None }, &[]).unwrap();
+            app.execute_contract(user, vault.clone(), &transfer, &[]).unwrap();
+            assert_eq!(vault_deposit(&app, &vault, &outsider, NATIVE_DENOM), Uint128::new(100));
+        }
+    }
+
+    mod governance {
+        use super::*;
+
+        #[test]
+        fn sudo_overrides() {
+            let (mut app, vault, _) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+            let new_admin = app.api().addr_make("governance_admin");
+            let deposit = ExecuteMsg::Deposit { expected: None, min_credit: None };
+
+            // Replace the admin without the admin key
+            app.wasm_sudo(vault.clone(), &SudoMsg::UpdateAdmin { admin: new_admin.to_string() }).unwrap();
+            let state: GetStateResponse = app.wrap().query_wasm_smart(&vault, &QueryMsg::GetState {}).unwrap();
+            assert_eq!(state.admin, Some(new_admin));
+
+            // Pause deposits
+            app.wasm_sudo(vault.clone(), &SudoMsg::Pause { operations: Some(vec![Operation::Deposit]), until: None }).unwrap();
+            let err = app.execute_contract(user.clone(), vault.clone(), &deposit, &coins(100, NATIVE_DENOM)).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::Paused { operation: "deposit".to_string() }.to_string());
+            app.wasm_sudo(vault.clone(), &SudoMsg::Pause { operations: Some(vec![Operation::Deposit]), until: Some(Expiration::AtHeight(app.block_info().height + 1)) })
+                .unwrap();
+            app.update_block(|block| block.height += 1);
+            app.execute_contract(user.clone(), vault.clone(), &deposit, &coins(100, NATIVE_DENOM)).unwrap();
+
+            // Lift a pause without the admin
+            app.wasm_sudo(vault.clone(), &SudoMsg::Pause { operations: None, until: None }).unwrap();
+            let err = app.execute_contract(user.clone(), vault.clone(), &deposit, &coins(100, NATIVE_DENOM)).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::Paused { operation: "deposit".to_string() }.to_string());
+            app.wasm_sudo(vault.clone(), &SudoMsg::Unpause { operations: None }).unwrap();
+            app.execute_contract(user.clone(), vault.clone(), &deposit, &coins(100, NATIVE_DENOM)).unwrap();
+
+            // Freeze an account
+            let msg = SudoMsg::Freeze { address: user.to_string(), reason: "court order".to_string() };
+            app.wasm_sudo(vault.clone(), &msg).unwrap();
+            let withdraw = ExecuteMsg::WithdrawAll { recipient: None };
+            let err = app.execute_contract(user.clone(), vault.clone(), &withdraw, &[]).unwrap_err();
+            assert_eq!(err.root_cause().to_string(), ContractError::AccountFrozen { address: user.to_string() }.to_string());
+            app.wasm_sudo(vault.clone(), &SudoMsg::Unfreeze { address: user.to_string() }).unwrap();
+            app.execute_contract(user.clone(), vault.clone(), &withdraw, &[]).unwrap();
+            assert_eq!(app.wrap().query_balance(&user, NATIVE_DENOM).unwrap().amount, Uint128::new(1000));
+        }
+    }
+
+    mod cw20_token {
+        use super::*;
+
+        #[test]
+        fn deposit_and_withdraw() {
+            let (mut app, vault, token) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+
+            let msg = Cw20ExecuteMsg::Send {
+                contract: vault.to_string(),
+                amount: Uint128::new(400),
+                msg: to_json_binary(&ReceiveMsg::Deposit {}).unwrap(),
+            };
+            app.execute_contract(user.clone(), token.clone(), &msg, &[]).unwrap();
+            assert_eq!(cw20_balance(&app, &token, &user), Uint128::new(600));
+            assert_eq!(vault_deposit(&app, &vault, &user, token.as_str()), Uint128::new(400));
+
+            let msg = ExecuteMsg::Withdraw { amount: Uint128::new(150), denom: token.to_string(), recipient: None, owner: None, memo: None, client_ref: None };
+            app.execute_contract(user.clone(), vault.clone(), &msg, &[]).unwrap();
+            assert_eq!(cw20_balance(&app, &token, &user), Uint128::new(750));
+            assert_eq!(cw20_balance(&app, &token, &vault), Uint128::new(250));
+            assert_eq!(vault_deposit(&app, &vault, &user, token.as_str()), Uint128::new(250));
+
+            // The tokens paid out by a full withdraw are reported as well
+            let res = app.execute_contract(user.clone(), vault.clone(), &ExecuteMsg::WithdrawAll { recipient: None }, &[]).unwrap();
+            let wasm = res.events.iter().find(|event| event.ty == "wasm").unwrap();
+            let amount = wasm.attributes.iter().find(|attr| attr.key == "amount").unwrap();
+            assert_eq!(amount.value, format!("250{}", token));
+            assert_eq!(cw20_balance(&app, &token, &user), Uint128::new(1000));
+        }
+
+        #[test]
+        fn unregistered_token_is_rejected() {
+            let (mut app, vault, _) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+            let other_token = instantiate_cw20(&mut app, "OTH");
+
+            let msg = Cw20ExecuteMsg::Send {
+                contract: vault.to_string(),
+                amount: Uint128::new(400),
+                msg: to_json_binary(&ReceiveMsg::Deposit {}).unwrap(),
+            };
+            let err = app.execute_contract(user.clone(), other_token.clone(), &msg, &[]).unwrap_err();
+            assert_eq!(
+                err.root_cause().to_string(),
+                ContractError::DenomNotFound { denom: other_token.to_string() }.to_string()
+            );
+            assert_eq!(cw20_balance(&app, &other_token, &user), Uint128::new(1000));
+        }
+
+        #[test]
+        fn vault_answers_cw20_queries() {
+            let (mut app, vault, token) = proper_instantiate();
+            let user = app.api().addr_make(USER);
+            let admin = app.api().addr_make(ADMIN);
+
+            let msg = Cw20ExecuteMsg::Send {
+                contract: vault.to_string(),
+                amount: Uint128::new(400),
+                msg: to_json_binary(&ReceiveMsg::Deposit {}).unwrap(),
+            };
+            app.execute_contract(user.clone(), token.clone(), &msg, &[]).unwrap();
+            let msg = ExecuteMsg::SetTokenInfo { denom: token.to_string(), name: None, symbol: Some("vTKN".to_string()), decimals: None };
+            app.execute_contract(admin, vault.clone(), &msg, &[]).unwrap();
+
+            // The vault reads like the token it holds
+            assert_eq!(cw20_balance(&app, &vault, &user), Uint128::new(400));
+            let info: TokenInfoResponse = app.wrap().query_wasm_smart(&vault, &Cw20QueryMsg::TokenInfo {}).unwrap();
+            assert_eq!(info.name, "TKN token");
+            assert_eq!(info.symbol, "vTKN");
+            assert_eq!(info.decimals, 6);
+            assert_eq!(info.total_supply, Uint128::new(400));
+            let accounts: AllAccountsResponse = app
+                .wrap()
+                .query_wasm_smart(&vault, &Cw20QueryMsg::AllAccounts { start_after: None, limit: None })
+                .unwrap();
+            assert_eq!(accounts.accounts, vec![user.to_string()]);
+        }
+    }
+}
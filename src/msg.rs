@@ -1,16 +1,159 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Coin, Order, Timestamp, Uint128};
+use cw20::Cw20ReceiveMsg;
+use cw_utils::Expiration;
 
 #[cw_serde]
 pub struct InstantiateMsg {
-    pub allowed_denom: String,
+    pub allowed_denoms: Vec<String>,
+    // Defaults to the instantiator when not provided
+    pub admin: Option<String>,
+    // Defaults to refunding the unexpected coins
+    pub unexpected_funds: Option<UnexpectedFundsPolicy>,
+}
+
+// What to do with coins sent with a deposit whose denom is not accepted by the vault
+#[cw_serde]
+#[derive(Default, Eq)]
+pub enum UnexpectedFundsPolicy {
+    // Reject the whole deposit
+    Strict,
+    // Send the coins back to the depositor
+    #[default]
+    Refund,
+}
+
+// Asset held by the vault, either a native denom or a CW20 token contract.
+// Its key (the denom or the contract address) identifies it in the "denom" fields of the messages.
+#[cw_serde]
+#[derive(Eq)]
+pub enum AssetInfo {
+    Native(String),
+    Cw20(Addr),
+}
+
+impl AssetInfo {
+    pub fn key(&self) -> &str {
+        match self {
+            AssetInfo::Native(denom) => denom,
+            AssetInfo::Cw20(contract) => contract.as_str(),
+        }
+    }
+}
+
+// Group of operations that can be paused
+#[cw_serde]
+#[derive(Copy, Eq)]
+pub enum Operation {
+    Deposit,
+    Transfer,
+    Withdraw,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::Deposit, Operation::Transfer, Operation::Withdraw];
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Operation::Deposit => "deposit",
+            Operation::Transfer => "transfer",
+            Operation::Withdraw => "withdraw",
+        }
+    }
+}
+
+// Denoms listed at instantiation are registered with default parameters
+#[cw_serde]
+#[derive(Default)]
+pub struct DenomParams {
+    pub min_deposit: Option<Uint128>,
+    pub max_per_account: Option<Uint128>,
+    pub label: Option<String>,
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    // Admin to set when migrating from a version that had no admin role
+    pub admin: Option<String>,
+}
+
+// Called by the chain governance, no admin key needed
+#[cw_serde]
+pub enum SudoMsg {
+    UpdateAdmin { admin: String },
+    Pause { operations: Option<Vec<Operation>>, until: Option<Expiration> },
+    Unpause { operations: Option<Vec<Operation>> },
+    Freeze { address: String, reason: String },
+    Unfreeze { address: String },
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    Deposit {}, //We could insert the amount to check if it is consistent with the fund sent to the bank module of the blockchain
-    Transfer {amount: Uint128, receiver: String},
-    Withdraw {amount: Uint128},
+    // Optional assertions on the amount credited for the single accepted denom sent with the deposit:
+    // exactly `expected`, or at least `min_credit`
+    Deposit {expected: Option<Uint128>, min_credit: Option<Uint128>},
+    // Credit the deposit to another address
+    DepositFor {recipient: String},
+    // Split a deposit of a single denom between several addresses, the amounts must sum to the funds sent
+    DepositForMany {recipients: Vec<(String, Uint128)>},
+    // An operator acts on the balance of the owner it was approved by, the sender's balance is used otherwise.
+    // The memo is stored in the ledger, a client reference can only be used once per sender.
+    Transfer {amount: Uint128, denom: String, receiver: String, owner: Option<String>, memo: Option<String>, client_ref: Option<String>},
+    // Funds are sent to the recipient when given, to the account withdrawn from otherwise
+    Withdraw {amount: Uint128, denom: String, recipient: Option<String>, owner: Option<String>, memo: Option<String>, client_ref: Option<String>},
+    // Withdraw the whole balance of the sender in every denom
+    WithdrawAll {recipient: Option<String>},
+    // Let an operator transfer and withdraw the whole balance of the sender, until revoked or expired
+    ApproveOperator {operator: String, expires: Option<Expiration>},
+    RevokeOperator {operator: String},
+    // Once shut down: withdraw the whole balance of the sender, paid pro rata of the holdings of
+    // the contract if they fall short of the deposits
+    EmergencyWithdraw {},
+    // Allow a spender to move up to an amount of the sender's balance of a denom
+    IncreaseAllowance {spender: String, denom: String, amount: Uint128, expires: Option<Expiration>},
+    DecreaseAllowance {spender: String, denom: String, amount: Uint128, expires: Option<Expiration>},
+    // Spend an allowance given by the owner, withdrawn funds are sent to the spender by default
+    TransferFrom {owner: String, receiver: String, amount: Uint128, denom: String, client_ref: Option<String>},
+    WithdrawFrom {owner: String, amount: Uint128, denom: String, recipient: Option<String>, client_ref: Option<String>},
+    // Deposit of CW20 tokens through the token's Send message
+    Receive(Cw20ReceiveMsg),
+    // Admin only: management of the accepted denoms
+    AddDenom {asset: AssetInfo, params: DenomParams},
+    UpdateDenom {denom: String, params: DenomParams},
+    DisableDenom {denom: String},
+    EnableDenom {denom: String},
+    // A denom can only be removed while no deposits of it are held
+    RemoveDenom {denom: String},
+    UpdateConfig {unexpected_funds: Option<UnexpectedFundsPolicy>},
+    // Admin only: denom exposed through the CW20 queries, metadata left empty is read from
+    // the bank denom metadata or the CW20 token info of the asset
+    SetTokenInfo {denom: String, name: Option<String>, symbol: Option<String>, decimals: Option<u8>},
+    // Admin or guardian: stop the operations, all of them when none are given, until unpaused or the expiry
+    Pause {operations: Option<Vec<Operation>>, until: Option<Expiration>},
+    // Admin only
+    Unpause {operations: Option<Vec<Operation>>},
+    SetGuardian {guardian: Option<String>},
+    // Admin only: cw4 group contract whose members only can receive deposits and transfers, None serves anyone
+    SetMembership {membership: Option<String>},
+    // Admin only: a frozen account can neither move its deposits nor receive any
+    Freeze {address: String, reason: String},
+    Unfreeze {address: String},
+    // Admin only: irreversibly close deposits, transfers and withdraws, leaving only EmergencyWithdraw
+    Shutdown {},
+    // Admin only: send what the contract holds above the deposits of each denom to the recipient
+    SweepSurplus {recipient: String},
+    UpdateAdmin {admin: String},
+    RenounceAdmin {},
+    // Two-step admin handover: the proposed owner must accept before the expiry
+    ProposeNewOwner {owner: String, expiry: Option<Expiration>},
+    AcceptOwnership {},
+    CancelOwnershipTransfer {},
+}
+
+// Message embedded in a CW20 Send to the vault
+#[cw_serde]
+pub enum ReceiveMsg {
+    Deposit {},
 }
 
 #[cw_serde]
@@ -19,32 +162,281 @@ pub enum QueryMsg {
     // GetCount returns the current count as a json-encoded number
     #[returns(GetStateResponse)]
     GetState {},
+    // Zero with exists set to false for owners that never deposited the denom
     #[returns(GetDepositResponse)]
-    GetDeposit {owner: Addr},
+    GetDeposit {owner: String, denom: String},
+    // Same as GetDeposit for several owners at once
+    #[returns(GetDepositsResponse)]
+    GetDeposits {owners: Vec<String>, denom: String},
+    // Deposits of one owner in every denom
+    #[returns(GetAccountDepositsResponse)]
+    GetAccountDeposits {owner: String},
+    // Optionally restricted to the owners holding a single denom, paginated by owner address
     #[returns(GetAllDepositResponse)]
-    GetAllDeposit {},
+    GetAllDeposit {
+        denom: Option<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+        // Defaults to ascending
+        order: Option<Order>,
+    },
     #[returns(GetTotalDepositResponse)]
-    GetTotalDeposit {},
+    GetTotalDeposit {denom: String},
+    // Balance at the start of the given block
+    #[returns(GetDepositAtHeightResponse)]
+    GetDepositAtHeight {owner: String, denom: String, height: u64},
+    #[returns(GetTotalDepositAtHeightResponse)]
+    GetTotalDepositAtHeight {denom: String, height: u64},
+    // Ledger entries of an account, oldest first
+    #[returns(GetHistoryResponse)]
+    GetHistory {address: String, start_after: Option<u64>, limit: Option<u32>},
+    // Operators approved by an owner, expired grants are skipped unless include_expired is set
+    #[returns(ListOperatorsResponse)]
+    ListOperators {owner: String, include_expired: Option<bool>, start_after: Option<String>, limit: Option<u32>},
+    // Ledger entry of the operation the sender made with the client reference
+    #[returns(GetClientRefResponse)]
+    GetClientRef {sender: String, client_ref: String},
+    #[returns(GetAllowanceResponse)]
+    GetAllowance {owner: String, spender: String, denom: String},
+    // Allowances given by an owner, paginated by (spender, denom)
+    #[returns(AllAllowancesResponse)]
+    AllAllowances {owner: String, start_after: Option<(String, String)>, limit: Option<u32>},
+    // Allowances received by a spender, paginated by (owner, denom)
+    #[returns(AllSpenderAllowancesResponse)]
+    AllSpenderAllowances {spender: String, start_after: Option<(String, String)>, limit: Option<u32>},
+    // CW20 compatible queries on the balances of the denom set with SetTokenInfo
+    #[returns(cw20::BalanceResponse)]
+    Balance {address: String},
+    #[returns(cw20::TokenInfoResponse)]
+    TokenInfo {},
+    #[returns(cw20::AllAccountsResponse)]
+    AllAccounts {start_after: Option<String>, limit: Option<u32>},
+    #[returns(GetPauseStatusResponse)]
+    GetPauseStatus {},
+    #[returns(ListFrozenResponse)]
+    ListFrozen {start_after: Option<String>, limit: Option<u32>},
+    // Holdings of the contract compared with the deposits, for every registered denom
+    #[returns(GetSolvencyResponse)]
+    GetSolvency {},
+    // Recomputes the total from the balances of a page of owners, summing `computed` over the pages gives the whole total
+    #[returns(AuditTotalDepositResponse)]
+    AuditTotalDeposit {denom: String, start_after: Option<String>, limit: Option<u32>},
+    #[returns(GetOwnershipResponse)]
+    GetOwnership {},
+    #[returns(ListDenomsResponse)]
+    ListDenoms {start_after: Option<String>, limit: Option<u32>},
+    #[returns(DenomInfoResponse)]
+    DenomInfo {denom: String},
 }
 
 // We define a custom struct for each query respons
 #[cw_serde]
 pub struct GetStateResponse {
-    pub allowed_denom: String,
+    // Registered denoms currently open for deposits
+    pub allowed_denoms: Vec<String>,
+    pub admin: Option<Addr>,
+    pub unexpected_funds: UnexpectedFundsPolicy,
+    pub guardian: Option<Addr>,
+    pub shutdown: bool,
+    pub membership: Option<Addr>,
 }
 #[cw_serde]
 pub struct GetDepositResponse {
     pub address: Addr,
+    pub denom: String,
     pub deposit: Uint128,
+    pub exists: bool,
 }
 #[cw_serde]
-pub struct GetAllDepositResponse {
+pub struct GetDepositsResponse {
+    pub deposits: Vec<GetDepositResponse>,
+}
+#[cw_serde]
+pub struct GetAccountDepositsResponse {
     pub address: Addr,
+    pub deposits: Vec<Coin>,
+}
+#[cw_serde]
+pub struct DepositEntry {
+    pub address: Addr,
+    pub denom: String,
     pub totaldeposit: Uint128,
 }
+#[cw_serde]
+pub struct GetAllDepositResponse {
+    pub deposits: Vec<DepositEntry>,
+    // Owner to pass as start_after for the next page, None on the last one
+    pub next_key: Option<String>,
+}
 
 #[cw_serde]
 pub struct GetTotalDepositResponse {
+    pub denom: String,
     pub totaldeposit: Uint128,
 }
 
+#[cw_serde]
+pub struct GetDepositAtHeightResponse {
+    pub address: Addr,
+    pub denom: String,
+    pub height: u64,
+    pub deposit: Uint128,
+}
+
+#[cw_serde]
+pub struct GetTotalDepositAtHeightResponse {
+    pub denom: String,
+    pub height: u64,
+    pub totaldeposit: Uint128,
+}
+
+#[cw_serde]
+pub struct AuditTotalDepositResponse {
+    pub denom: String,
+    pub stored: Uint128,
+    pub computed: Uint128,
+    // Only known when a single page covers every owner
+    pub consistent: Option<bool>,
+    pub next_key: Option<String>,
+}
+
+#[cw_serde]
+pub struct DenomInfoResponse {
+    pub denom: String,
+    pub asset: AssetInfo,
+    pub enabled: bool,
+    pub min_deposit: Option<Uint128>,
+    pub max_per_account: Option<Uint128>,
+    pub label: Option<String>,
+}
+
+#[cw_serde]
+pub struct ListDenomsResponse {
+    pub denoms: Vec<DenomInfoResponse>,
+}
+
+#[cw_serde]
+pub struct GetOwnershipResponse {
+    pub owner: Option<Addr>,
+    pub pending_owner: Option<Addr>,
+    pub pending_expiry: Option<Expiration>,
+}
+
+#[cw_serde]
+#[derive(Eq)]
+pub enum LedgerKind {
+    Deposit,
+    TransferIn,
+    TransferOut,
+    Withdraw,
+}
+
+// Change to the balance of an account, in the order the contract applied it
+#[cw_serde]
+pub struct LedgerEntry {
+    pub id: u64,
+    pub kind: LedgerKind,
+    // Other side of the movement: the sender of a deposit made for the account, the other party
+    // of a transfer, the recipient of a withdraw sent elsewhere
+    pub counterparty: Option<Addr>,
+    pub amount: Coin,
+    pub height: u64,
+    pub time: Timestamp,
+    pub memo: Option<String>,
+}
+
+#[cw_serde]
+pub struct GetHistoryResponse {
+    pub entries: Vec<LedgerEntry>,
+}
+
+#[cw_serde]
+pub struct GetClientRefResponse {
+    pub sender: Addr,
+    pub client_ref: String,
+    // None if the reference was never used
+    pub entry: Option<LedgerEntry>,
+}
+
+#[cw_serde]
+pub struct GetAllowanceResponse {
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct AllowanceInfo {
+    pub spender: Addr,
+    pub denom: String,
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct AllAllowancesResponse {
+    pub allowances: Vec<AllowanceInfo>,
+}
+
+#[cw_serde]
+pub struct SpenderAllowanceInfo {
+    pub owner: Addr,
+    pub denom: String,
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct AllSpenderAllowancesResponse {
+    pub allowances: Vec<SpenderAllowanceInfo>,
+}
+
+#[cw_serde]
+pub struct OperatorInfo {
+    pub operator: Addr,
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct ListOperatorsResponse {
+    pub operators: Vec<OperatorInfo>,
+}
+
+#[cw_serde]
+pub struct FrozenAccount {
+    pub address: Addr,
+    pub reason: String,
+    pub since: Timestamp,
+}
+
+#[cw_serde]
+pub struct ListFrozenResponse {
+    pub accounts: Vec<FrozenAccount>,
+}
+
+#[cw_serde]
+pub struct DenomSolvency {
+    pub denom: String,
+    // Balance of the contract in the bank or in the CW20 token
+    pub held: Uint128,
+    pub deposits: Uint128,
+    pub surplus: Uint128,
+    pub deficit: Uint128,
+}
+
+#[cw_serde]
+pub struct GetSolvencyResponse {
+    pub denoms: Vec<DenomSolvency>,
+    pub solvent: bool,
+}
+
+#[cw_serde]
+pub struct OperationStatus {
+    pub operation: Operation,
+    pub paused: bool,
+    pub until: Option<Expiration>,
+}
+
+#[cw_serde]
+pub struct GetPauseStatusResponse {
+    pub operations: Vec<OperationStatus>,
+}
@@ -1,13 +1,113 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Empty, Timestamp, Uint128};
+use cw_storage_plus::{Item, Map, SnapshotMap, Strategy};
+use cw_utils::Expiration;
+
+use crate::msg::{AssetInfo, DenomParams, LedgerEntry, UnexpectedFundsPolicy};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct Config {
-    pub allowed_denom: String,
+    // None once the admin role has been renounced
+    pub admin: Option<Addr>,
+    #[serde(default)]
+    pub unexpected_funds: UnexpectedFundsPolicy,
+    // May pause operations, only the admin can unpause
+    #[serde(default)]
+    pub guardian: Option<Addr>,
+    // Set for good by Shutdown, only emergency withdraws remain open
+    #[serde(default)]
+    pub shutdown: bool,
+    // cw4 group whose members only may be credited, anyone when unset
+    #[serde(default)]
+    pub membership: Option<Addr>,
+}
+
+// Registry entry of a denom accepted by the vault
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct DenomInfo {
+    pub asset: AssetInfo,
+    // Disabled denoms can no longer be deposited but can still be withdrawn
+    pub enabled: bool,
+    pub min_deposit: Option<Uint128>,
+    pub max_per_account: Option<Uint128>,
+    pub label: Option<String>,
+}
+
+// Amount of a denom a spender may move out of the balance of an owner
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Allowance {
+    pub amount: Uint128,
+    pub expires: Expiration,
+}
+
+// Denom shown through the CW20 queries, with metadata overriding the one of the underlying asset
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TokenConfig {
+    pub denom: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+}
+
+// Why and since when an account is frozen
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct FreezeInfo {
+    pub reason: String,
+    pub since: Timestamp,
+}
+
+// Ownership offer made by the admin, waiting to be accepted
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingOwner {
+    pub owner: Addr,
+    pub expiry: Expiration,
+}
+
+impl DenomInfo {
+    pub fn new(asset: AssetInfo, params: DenomParams) -> Self {
+        DenomInfo {
+            asset,
+            enabled: true,
+            min_deposit: params.min_deposit,
+            max_per_account: params.max_per_account,
+            label: params.label,
+        }
+    }
 }
 
 pub const CONFIG: Item<Config> = Item::new("state");
-pub const BALANCES: Map<Addr, Uint128> = Map::new("balances");
+pub const PENDING_OWNER: Item<PendingOwner> = Item::new("pending_owner");
+pub const TOKEN: Item<TokenConfig> = Item::new("token");
+pub const DENOMS: Map<&str, DenomInfo> = Map::new("denoms");
+// Deposits keyed by (owner, asset key), with a snapshot of every block they change in
+pub const BALANCES: SnapshotMap<(&Addr, &str), Uint128> = SnapshotMap::new(
+    "deposits",
+    "deposits__checkpoints",
+    "deposits__changelog",
+    Strategy::EveryBlock,
+);
+// Owners with a non-zero deposit keyed by (asset key, owner), to list one denom without walking every deposit
+pub const HOLDERS: Map<(&str, &Addr), Empty> = Map::new("holders");
+// Sum of the deposits of every owner, per asset key
+pub const TOTAL_DEPOSITS: SnapshotMap<&str, Uint128> = SnapshotMap::new(
+    "total_deposits",
+    "total_deposits__checkpoints",
+    "total_deposits__changelog",
+    Strategy::EveryBlock,
+);
+// Append-only ledger keyed by (account, entry id), ids are shared by all accounts
+pub const LEDGER_SEQ: Item<u64> = Item::new("ledger_seq");
+pub const HISTORY: Map<(&Addr, u64), LedgerEntry> = Map::new("history");
+// Account acted on and its ledger entry id, for the operation made with each (sender, client reference)
+pub const CLIENT_REFS: Map<(&Addr, &str), (Addr, u64)> = Map::new("client_refs");
+// Allowances keyed by (owner, spender, asset key), and the same keyed by (spender, owner, asset key)
+pub const ALLOWANCES: Map<(&Addr, &Addr, &str), Allowance> = Map::new("allowances");
+pub const ALLOWANCES_SPENDER: Map<(&Addr, &Addr, &str), Allowance> = Map::new("allowances_spender");
+// Operators keyed by (owner, operator), with the expiry of the grant
+pub const OPERATORS: Map<(&Addr, &Addr), Expiration> = Map::new("operators");
+// Paused operations keyed by Operation::key, until the expiry
+pub const PAUSED: Map<&str, Expiration> = Map::new("paused");
+// Accounts that can no longer move their deposits
+pub const FROZEN: Map<&Addr, FreezeInfo> = Map::new("frozen");